@@ -0,0 +1,203 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use tempfile::NamedTempFile;
+use crate::hash::{hash_file, FileHashes};
+
+#[derive(thiserror::Error, Debug)]
+pub enum CacheError {
+    #[error("could not find a cache directory, set PACK_IT_CACHE_DIR to choose one")]
+    NoCacheDir,
+}
+
+/// A cache of downloaded files shared between packs, stored under `objects/` by the strongest hash of each file.
+pub struct Cache {
+    objects: PathBuf,
+}
+
+pub struct GcResult {
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+impl Cache {
+    /// Opens the cache in `$PACK_IT_CACHE_DIR`, or `pack-it` in the platform's cache directory.
+    pub fn open() -> Result<Self> {
+        let root = env::var_os("PACK_IT_CACHE_DIR").map(PathBuf::from)
+            .or_else(|| env::var_os("XDG_CACHE_HOME").map(|dir| PathBuf::from(dir).join("pack-it")))
+            .or_else(|| env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("pack-it").join("cache")))
+            .or_else(|| env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".cache").join("pack-it")))
+            .ok_or(CacheError::NoCacheDir)?;
+        Ok(Self {
+            objects: root.join("objects"),
+        })
+    }
+
+    /// Creates the cache directory if it doesn't exist yet.
+    pub fn create_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.objects)?;
+        Ok(())
+    }
+
+    fn object_path(&self, hashes: &FileHashes) -> Option<PathBuf> {
+        hashes.strongest().map(|(_, hash)| self.objects.join(hash))
+    }
+
+    /// Installs the cached copy of a file at `output`, returning false if it is not cached. Objects are checked
+    /// before they are used, and any that don't match their hash are thrown away.
+    pub fn install(&self, hashes: &FileHashes, output: &Path) -> Result<bool> {
+        let (algorithm, hash) = match hashes.strongest() {
+            Some(strongest) => strongest,
+            None => return Ok(false),
+        };
+        let object = self.objects.join(hash);
+        if !object.exists() {
+            return Ok(false);
+        }
+        if hash_file(&object, &[algorithm])?.get(algorithm) != Some(hash) {
+            fs::remove_file(&object)?;
+            return Ok(false);
+        }
+
+        if output.exists() {
+            fs::remove_file(output)?;
+        }
+        if fs::hard_link(&object, output).is_err() {
+            fs::copy(&object, output)?;
+        }
+        touch(&object)?;
+        Ok(true)
+    }
+
+    /// Adds a verified file to the cache, if it is not there already.
+    pub fn insert(&self, hashes: &FileHashes, file: &Path) -> Result<()> {
+        let object = match self.object_path(hashes) {
+            Some(object) => object,
+            None => return Ok(()),
+        };
+        if object.exists() {
+            return touch(&object);
+        }
+
+        fs::create_dir_all(&self.objects)?;
+        // a hard link appears all at once, but a copy is written under a temporary name first, so that a
+        // half-written object is never picked up
+        match fs::hard_link(file, &object) {
+            Ok(()) => return Ok(()),
+            // another download of the same file got there first
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+            Err(_) => {}
+        }
+        let mut temp = NamedTempFile::new_in(&self.objects)?;
+        io::copy(&mut fs::File::open(file)?, temp.as_file_mut())?;
+        // temporary files are only readable by their owner, but installed copies should be readable like any other
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp.as_file().set_permissions(fs::Permissions::from_mode(0o644))?;
+        }
+        temp.persist(&object)?;
+        Ok(())
+    }
+
+    /// Removes objects that have not been used for `max_age`. With `unused`, objects that are not hard-linked into
+    /// any pack are removed as well.
+    pub fn gc(&self, max_age: Duration, unused: bool) -> Result<GcResult> {
+        let mut result = GcResult {
+            removed: 0,
+            freed_bytes: 0,
+        };
+        if !self.objects.is_dir() {
+            return Ok(result);
+        }
+
+        let now = SystemTime::now();
+        for entry in fs::read_dir(&self.objects)? {
+            let entry = entry?;
+            // leave alone anything that is still being added to the cache
+            if !is_object(&entry.file_name()) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+            if age > max_age || (unused && !is_linked(&metadata)) {
+                fs::remove_file(entry.path())?;
+                result.removed += 1;
+                result.freed_bytes += metadata.len();
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Checks whether a file in the cache is a finished object, which are named after their hash.
+fn is_object(file_name: &OsStr) -> bool {
+    file_name.to_str().is_some_and(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Marks an object as used, so that `cache gc` keeps it.
+fn touch(object: &Path) -> Result<()> {
+    fs::File::options().write(true).open(object)?.set_modified(SystemTime::now())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+// Without link counts there is no way to tell whether a pack still uses an object
+#[cfg(not(unix))]
+fn is_linked(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{FileHasher, HashAlgorithm};
+
+    fn hashes(content: &[u8]) -> FileHashes {
+        let mut hasher = FileHasher::new(&[HashAlgorithm::Sha1]);
+        hasher.update(content);
+        hasher.finish()
+    }
+
+    #[test]
+    fn installs_inserted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache { objects: dir.path().join("objects") };
+        let file = dir.path().join("mod.jar");
+        fs::write(&file, b"mod").unwrap();
+
+        cache.insert(&hashes(b"mod"), &file).unwrap();
+        // inserting the same file again is fine
+        cache.insert(&hashes(b"mod"), &file).unwrap();
+
+        let output = dir.path().join("installed.jar");
+        assert!(cache.install(&hashes(b"mod"), &output).unwrap());
+        assert_eq!(fs::read(&output).unwrap(), b"mod");
+        assert!(!cache.install(&hashes(b"other"), &dir.path().join("other.jar")).unwrap());
+    }
+
+    // objects are only removed as unused where link counts are available
+    #[cfg(unix)]
+    #[test]
+    fn gc_leaves_unfinished_objects_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache { objects: dir.path().to_path_buf() };
+        let object = dir.path().join(hashes(b"mod").strongest().unwrap().1);
+        fs::write(&object, b"mod").unwrap();
+        let unfinished = NamedTempFile::new_in(dir.path()).unwrap();
+
+        let result = cache.gc(Duration::from_secs(3600), true).unwrap();
+        assert_eq!(result.removed, 1);
+        assert!(!object.exists());
+        assert!(unfinished.path().exists());
+    }
+}
@@ -1,45 +1,107 @@
 use anyhow::Result;
 use std::path::Path;
-use reqwest::Client;
-use sha1::{Sha1, Digest};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{Client, Response, StatusCode};
+use reqwest::header::{CONTENT_RANGE, RANGE, RETRY_AFTER};
 use std::fs;
-use std::io::Write;
-use crate::util::{complete, info};
+use std::io::{self, Seek, SeekFrom, Write};
+use tokio::time::timeout;
+use tempfile::NamedTempFile;
+use futures::stream::{self, StreamExt};
+use crate::cache::Cache;
+use crate::hash::{hash_file, FileHasher, FileHashes, HashAlgorithm};
+use crate::pack::ModMetadata;
+use crate::util::{complete, ensure_online, info, warning};
 
 #[derive(thiserror::Error, Debug)]
 pub enum DownloadError {
-    #[error("expected file hash: {0} but downloaded file with hash: {1}")]
-    InvalidHash(String, String),
+    #[error("expected {0} hash: {1} but downloaded file with hash: {2}")]
+    InvalidHash(&'static str, String, String),
+    #[error("no supported hash is known for the file")]
+    MissingHash,
+    #[error("failed to download {url}: {reason}")]
+    Network {
+        url: String,
+        reason: String,
+    },
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Servers can ask for a long wait with Retry-After, but there is no point waiting around for more than this.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+pub struct DownloadOptions {
+    /// How many times a failed download is retried before giving up.
+    pub retries: u32,
+    /// How long to wait for the server to respond, or to send more data, before trying again.
+    pub timeout: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            timeout: Duration::from_secs(30),
+        }
+    }
 }
 
 pub struct Downloader {
     client: Client,
+    cache: Option<Cache>,
+    options: DownloadOptions,
 }
 
 impl Downloader {
+    /// Creates a downloader that uses the shared download cache, if there is somewhere to put it.
     pub fn new() -> Self {
+        Self::with_options(DownloadOptions::default())
+    }
+
+    pub fn with_options(options: DownloadOptions) -> Self {
         Self {
             client: Client::new(),
+            cache: Cache::open().ok().filter(|cache| match cache.create_dir() {
+                Ok(()) => true,
+                Err(e) => {
+                    warning(&format!("Not using the download cache: {}", e));
+                    false
+                }
+            }),
+            options,
         }
     }
 
-    pub async fn download_if_hash_invalid(&self, output: &Path, url: &str, hash: &str) -> Result<()> {
+    /// Downloads the given mods, running up to `jobs` downloads at once. A failed download does not stop the others,
+    /// and the result for every mod is returned.
+    pub async fn download_mods(&self, mods: Vec<ModMetadata>, jobs: usize) -> Vec<(ModMetadata, Result<()>)> {
+        stream::iter(mods)
+            .map(|mod_metadata| async move {
+                let path = Path::new(&mod_metadata.output_path);
+                let result = self.download_if_hash_invalid(path, &mod_metadata.download_urls(), &mod_metadata.hashes).await;
+                (mod_metadata, result)
+            })
+            .buffer_unordered(jobs.max(1))
+            .collect()
+            .await
+    }
+
+    /// Downloads a file to `output` unless it is already there, verifying it with the strongest of `hashes`. Each of
+    /// `urls` is tried in turn until one of them gives the right file.
+    pub async fn download_if_hash_invalid(&self, output: &Path, urls: &[&str], hashes: &FileHashes) -> Result<()> {
+        let (algorithm, hash) = hashes.strongest().ok_or(DownloadError::MissingHash)?;
         if output.exists() {
-            let data = fs::read(output)?;
-            let file_hash = Sha1::digest(&*data);
-            let file_hash = format!("{:02x}", file_hash);
-            if file_hash == hash {
-                complete(&*format!("{:?} is already ok!", output));
+            let file_hash = hash_file(output, &[algorithm])?;
+            if file_hash.get(algorithm) == Some(hash) {
+                self.add_to_cache(hashes, output);
+                complete(&format!("{:?} is already ok!", output));
                 return Ok(());
             }
-        }
-
-        info(&*format!("Downloading {}...", url));
-        let data = self.client.get(url).send().await?.bytes().await?;
-        let digest = Sha1::digest(&*data);
-        let download_hash = format!("{:02x}", digest);
-        if download_hash != hash {
-            return Err(DownloadError::InvalidHash(hash.to_string(), download_hash).into());
+            // remove the stale file now, so that it is not left behind if the download fails
+            fs::remove_file(output)?;
         }
 
         let parent = output.parent().expect("File does not have a parent");
@@ -47,12 +109,273 @@ impl Downloader {
             fs::create_dir_all(parent)?;
         }
 
-        let mut output_file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&output)?;
-        output_file.write_all(&*data)?;
+        if let Some(cache) = &self.cache {
+            match cache.install(hashes, output) {
+                Ok(true) => {
+                    complete(&format!("{:?} was copied from the cache!", output));
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(e) => warning(&format!("Could not copy {:?} from the download cache: {}", output, e)),
+            }
+        }
+        let (first_url, mirrors) = urls.split_first().expect("no URLs to download from");
+        ensure_online(first_url)?;
+
+        let mut result = self.download_from(first_url, output, algorithm, hash).await;
+        for mirror in mirrors {
+            if let Err(e) = &result {
+                warning(&format!("{}, trying {} instead...", e, mirror));
+                result = self.download_from(mirror, output, algorithm, hash).await;
+            }
+        }
+        result?;
+
+        self.add_to_cache(hashes, output);
+        Ok(())
+    }
+
+    /// Adds a verified file to the download cache. The cache is only there to save downloads, so failing to add
+    /// to it is not an error.
+    fn add_to_cache(&self, hashes: &FileHashes, file: &Path) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.insert(hashes, file) {
+                warning(&format!("Could not add {:?} to the download cache: {}", file, e));
+            }
+        }
+    }
+
+    async fn download_from(&self, url: &str, output: &Path, algorithm: HashAlgorithm, hash: &str) -> Result<()> {
+        let parent = output.parent().expect("File does not have a parent");
+
+        // Download next to the output so the final rename stays on the same filesystem. If anything goes wrong
+        // before the rename, the temporary file is deleted when it is dropped.
+        let mut temp_file = NamedTempFile::new_in(parent)?;
+        let mut progress = Progress {
+            hasher: FileHasher::new(&[algorithm]),
+            downloaded: 0,
+        };
+
+        info(&format!("Downloading {}...", url));
+        let mut attempt = 0;
+        loop {
+            match self.fetch(url, temp_file.as_file_mut(), &mut progress, algorithm).await {
+                Ok(()) => break,
+                Err(Attempt::Fatal(e)) => return Err(e),
+                Err(Attempt::Retry(error, retry_after)) => {
+                    if attempt >= self.options.retries {
+                        return Err(error.into());
+                    }
+                    let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+                    warning(&format!("{}, retrying in {:.1}s...", error, delay.as_secs_f64()));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+        let download_hashes = progress.hasher.finish();
+        let download_hash = download_hashes.get(algorithm).expect("hasher did not compute the requested hash");
+        if download_hash != hash {
+            return Err(DownloadError::InvalidHash(algorithm.name(), hash.to_string(), download_hash.to_string()).into());
+        }
+
+        temp_file.as_file().sync_all()?;
+        // temporary files are only readable by their owner, but the mod should be readable like any other file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp_file.as_file().set_permissions(fs::Permissions::from_mode(0o644))?;
+        }
+        temp_file.persist(output)?;
+        Ok(())
+    }
+
+    /// Makes one attempt at downloading `url` into `file`, carrying on from where the last attempt left off if the
+    /// server supports range requests.
+    async fn fetch(&self, url: &str, file: &mut fs::File, progress: &mut Progress, algorithm: HashAlgorithm) -> Result<(), Attempt> {
+        let mut request = self.client.get(url);
+        if progress.downloaded > 0 {
+            info(&format!("Resuming {} from {} bytes...", url, progress.downloaded));
+            request = request.header(RANGE, format!("bytes={}-", progress.downloaded));
+        }
+
+        let response = match timeout(self.options.timeout, request.send()).await {
+            Err(_) => return Err(Attempt::Retry(self.timed_out(url), None)),
+            Ok(Err(e)) if e.is_builder() => return Err(Attempt::Fatal(e.into())),
+            Ok(Err(e)) => return Err(Attempt::Retry(network_error(url, e.to_string()), None)),
+            Ok(Ok(response)) => response,
+        };
+
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && progress.downloaded > 0 {
+            progress.restart(file, algorithm)?;
+            return Err(Attempt::Retry(network_error(url, "server could not resume the download".to_string()), Some(Duration::from_secs(0))));
+        }
+        if !status.is_success() {
+            let error = network_error(url, format!("server responded with {}", status));
+            return Err(if is_retryable(status) {
+                Attempt::Retry(error, retry_after(&response))
+            } else {
+                Attempt::Fatal(error.into())
+            });
+        }
+        if progress.downloaded > 0 && status != StatusCode::PARTIAL_CONTENT {
+            // the server ignored the range and is sending the whole file again
+            progress.restart(file, algorithm)?;
+        }
+        if progress.downloaded > 0 && content_range_start(&response) != Some(progress.downloaded) {
+            // the server sent a different part of the file than was asked for, so start again from the beginning
+            progress.restart(file, algorithm)?;
+            return Err(Attempt::Retry(network_error(url, "server sent the wrong part of the file".to_string()), Some(Duration::from_secs(0))));
+        }
+
+        let mut body = response.bytes_stream();
+        loop {
+            let chunk = match timeout(self.options.timeout, body.next()).await {
+                Err(_) => return Err(Attempt::Retry(self.timed_out(url), None)),
+                Ok(None) => return Ok(()),
+                Ok(Some(Err(e))) => return Err(Attempt::Retry(network_error(url, e.to_string()), None)),
+                Ok(Some(Ok(chunk))) => chunk,
+            };
+            progress.hasher.update(&chunk);
+            file.write_all(&chunk)?;
+            progress.downloaded += chunk.len() as u64;
+        }
+    }
+
+    fn timed_out(&self, url: &str) -> DownloadError {
+        network_error(url, format!("no response for {} seconds", self.options.timeout.as_secs()))
+    }
+}
+
+/// How much of a file has been downloaded so far, so that retries can resume it.
+struct Progress {
+    hasher: FileHasher,
+    downloaded: u64,
+}
 
+impl Progress {
+    fn restart(&mut self, file: &mut fs::File, algorithm: HashAlgorithm) -> io::Result<()> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        self.hasher = FileHasher::new(&[algorithm]);
+        self.downloaded = 0;
         Ok(())
     }
 }
+
+enum Attempt {
+    /// A failure that might go away, optionally with how long the server asked us to wait.
+    Retry(DownloadError, Option<Duration>),
+    Fatal(anyhow::Error),
+}
+
+impl From<io::Error> for Attempt {
+    fn from(e: io::Error) -> Self {
+        Attempt::Fatal(e.into())
+    }
+}
+
+fn network_error(url: &str, reason: String) -> DownloadError {
+    DownloadError::Network {
+        url: url.to_string(),
+        reason,
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// Exponential backoff with up to 50% jitter, so that parallel downloads don't all retry at the same moment.
+fn backoff(attempt: u32) -> Duration {
+    let delay = INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF);
+    delay.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..0.5))
+}
+
+/// Reads where a partial response starts from its `Content-Range` header, eg. `bytes 100-999/1000`.
+fn content_range_start(response: &Response) -> Option<u64> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = value.trim().strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Reads a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => (DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default(),
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    const CONTENT: &[u8] = b"0123456789";
+
+    fn downloader() -> Downloader {
+        Downloader {
+            client: Client::new(),
+            cache: None,
+            options: DownloadOptions {
+                retries: 3,
+                timeout: Duration::from_secs(5),
+            },
+        }
+    }
+
+    fn hashes() -> FileHashes {
+        let mut hasher = FileHasher::new(&[HashAlgorithm::Sha1]);
+        hasher.update(CONTENT);
+        hasher.finish()
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\n{}Connection: close\r\n\r\n", status, headers).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// A full response that claims the whole file but stops halfway, so the download has to be resumed.
+    fn cut_off() -> Vec<u8> {
+        response("200 OK", &format!("Content-Length: {}\r\n", CONTENT.len()), &CONTENT[..5])
+    }
+
+    async fn download(responses: Vec<Vec<u8>>) -> (Result<()>, Vec<u8>, Vec<String>) {
+        let (url, requests) = test_server::serve_responses(responses).await;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("mod.jar");
+        let result = downloader().download_if_hash_invalid(&output, &[&format!("{}/mod.jar", url)], &hashes()).await;
+        let content = fs::read(&output).unwrap_or_default();
+        let requests = requests.lock().unwrap().clone();
+        (result, content, requests)
+    }
+
+    #[tokio::test]
+    async fn resumes_from_where_it_stopped() {
+        let (result, content, requests) = download(vec![
+            cut_off(),
+            response("206 Partial Content", "Content-Range: bytes 5-9/10\r\nContent-Length: 5\r\n", &CONTENT[5..]),
+        ]).await;
+        result.unwrap();
+        assert_eq!(content, CONTENT);
+        assert!(requests[1].to_lowercase().contains("range: bytes=5-"));
+    }
+
+    #[tokio::test]
+    async fn starts_again_if_the_wrong_range_is_sent() {
+        let (result, content, requests) = download(vec![
+            cut_off(),
+            response("206 Partial Content", "Content-Range: bytes 3-9/10\r\nContent-Length: 7\r\n", &CONTENT[3..]),
+            response("200 OK", &format!("Content-Length: {}\r\n", CONTENT.len()), CONTENT),
+        ]).await;
+        result.unwrap();
+        assert_eq!(content, CONTENT);
+        assert_eq!(requests.len(), 3);
+        assert!(!requests[2].to_lowercase().contains("range:"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use serde::{Serialize, Deserialize};
+use zip::{ZipArchive, ZipWriter};
+use crate::formats::{add_zip_dir, add_zip_file, copy_dir, download_all, extract_zip_dir, primary_game_version, relative_path, FormatError, OVERRIDES_DIR};
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata, ModSpec, SourceKind};
+use crate::sources::ModSource;
+use crate::sources::curseforge::CurseforgeClient;
+use crate::util::{info, warning};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseforgeManifest {
+    pub minecraft: CurseforgeManifestMinecraft,
+    pub manifest_type: String,
+    pub manifest_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String,
+    pub files: Vec<CurseforgeManifestFile>,
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseforgeManifestMinecraft {
+    pub version: String,
+    pub mod_loaders: Vec<CurseforgeManifestModLoader>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CurseforgeManifestModLoader {
+    /// The loader and its version, eg. `fabric-0.11.3`
+    pub id: String,
+    pub primary: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CurseforgeManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: i32,
+    #[serde(rename = "fileID")]
+    pub file_id: i32,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_string()
+}
+
+fn default_required() -> bool {
+    true
+}
+
+/// Writes the pack as a CurseForge modpack zip. Mods that are not from CurseForge are bundled into
+/// `overrides/` if `bundle_other` is set, and are otherwise left out and reported.
+pub async fn export(pack: &ModPack, output: &Path, bundle_other: bool) -> Result<()> {
+    let game_version = primary_game_version(pack)?;
+    let loader_version = pack.loader_version().ok_or(FormatError::MissingLoaderVersion)?;
+
+    let mods = pack.get_mods();
+    let mut files = vec![];
+    let mut others = vec![];
+    for mod_metadata in mods {
+        match &mod_metadata.update_info {
+            Some(ModUpdateMetadata::Curseforge { addon_id, file_id }) => files.push(CurseforgeManifestFile {
+                project_id: *addon_id,
+                file_id: *file_id,
+                required: pack.optional_group(&mod_metadata.name).is_none(),
+            }),
+            _ => others.push(mod_metadata),
+        }
+    }
+
+    let mut bundled = vec![];
+    if bundle_other {
+        download_all(&others).await?;
+        for mod_metadata in &others {
+            bundled.push((mod_metadata.name.clone(), relative_path(&mod_metadata.output_path)?, fs::read(&mod_metadata.output_path)?));
+        }
+    }
+
+    let manifest = CurseforgeManifest {
+        minecraft: CurseforgeManifestMinecraft {
+            version: game_version.to_string(),
+            mod_loaders: vec![CurseforgeManifestModLoader {
+                id: format!("{}-{}", pack.mod_loader(), loader_version),
+                primary: true,
+            }],
+        },
+        manifest_type: "minecraftModpack".to_string(),
+        manifest_version: 1,
+        name: pack.pack_name().to_string(),
+        version: pack.pack_version().to_string(),
+        author: pack.author().unwrap_or_default().to_string(),
+        files,
+        overrides: default_overrides(),
+    };
+
+    let mut zip = ZipWriter::new(fs::File::create(output)?);
+    add_zip_file(&mut zip, MANIFEST_FILE, serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    add_zip_dir(&mut zip, Path::new(OVERRIDES_DIR), &manifest.overrides)?;
+    for (name, path, data) in bundled {
+        info(&format!("Bundling {} into the overrides", name));
+        add_zip_file(&mut zip, &format!("{}/{}", manifest.overrides, path), &data)?;
+    }
+    zip.finish()?;
+
+    if !bundle_other {
+        for mod_metadata in others {
+            warning(&format!("{} is not from CurseForge, so it was left out (use --bundle-other to include it in the overrides)", mod_metadata.name));
+        }
+    }
+    Ok(())
+}
+
+/// Reads a CurseForge modpack (either the zip or a bare `manifest.json`) into a new pack, copying its overrides
+/// into `destination`.
+pub async fn import(file: &Path, destination: &Path) -> Result<ModPack> {
+    let is_manifest = file.extension().is_some_and(|extension| extension == "json");
+    let mut zip = if is_manifest { None } else { Some(ZipArchive::new(fs::File::open(file)?)?) };
+    let manifest: CurseforgeManifest = match &mut zip {
+        Some(zip) => serde_json::from_reader(zip.by_name(MANIFEST_FILE)?)?,
+        None => serde_json::from_reader(fs::File::open(file)?)?,
+    };
+
+    let loader = manifest.minecraft.mod_loaders.iter()
+        .find(|loader| loader.primary)
+        .or_else(|| manifest.minecraft.mod_loaders.first())
+        .ok_or_else(|| FormatError::UnsupportedLoader("none".to_string()))?;
+    let (mod_loader, loader_version) = loader.id.split_once('-')
+        .ok_or_else(|| FormatError::UnsupportedLoader(loader.id.clone()))?;
+
+    let mut pack = ModPack::new(manifest.name.clone(), Some(manifest.author.clone()).filter(|a| !a.is_empty()),
+                                vec![manifest.minecraft.version.clone()], mod_loader.to_string());
+    pack.set_loader_version(Some(loader_version.to_string()));
+    if !manifest.version.is_empty() {
+        pack.set_pack_version(Some(manifest.version.clone()));
+    }
+
+    let cf_client = &CurseforgeClient::new()?;
+    let results: Vec<Result<Option<(ModMetadata, bool)>>> = stream::iter(&manifest.files)
+        .map(|file| async move { Ok(resolve_file(cf_client, file).await?.map(|mod_metadata| (mod_metadata, file.required))) })
+        .buffer_unordered(8)
+        .collect()
+        .await;
+    for result in results {
+        if let Some((mod_metadata, required)) = result? {
+            info(&format!("Imported {}", mod_metadata.name));
+            let spec = ModSpec {
+                optional: !required,
+                ..ModSpec::new(SourceKind::Curseforge, mod_metadata.name.clone(), None)
+            };
+            pack.add(spec, mod_metadata);
+        }
+    }
+
+    let extracted = match &mut zip {
+        Some(zip) => extract_zip_dir(zip, &manifest.overrides, destination)?,
+        None => copy_dir(&file.with_file_name(&manifest.overrides), destination)?,
+    };
+    info(&format!("Copied {} files from the overrides", extracted));
+    Ok(pack)
+}
+
+async fn resolve_file(cf_client: &CurseforgeClient, file: &CurseforgeManifestFile) -> Result<Option<ModMetadata>> {
+    let update_info = ModUpdateMetadata::Curseforge {
+        addon_id: file.project_id,
+        file_id: file.file_id,
+    };
+    match cf_client.fetch_pinned(&update_info).await? {
+        None => {
+            warning(&format!("File {} of project {} could not be found, skipping!", file.file_id, file.project_id));
+            Ok(None)
+        }
+        Some(resolved) => Ok(Some(resolved.into_metadata().await?)),
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path};
+use anyhow::Result;
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::download::Downloader;
+use crate::pack::{ModPack, ModMetadata};
+use crate::util::error;
+
+pub mod mrpack;
+pub mod curseforge;
+pub mod packwiz;
+pub mod prism;
+
+/// The directory next to pack.toml whose contents are copied into the `overrides/` of exported packs.
+pub const OVERRIDES_DIR: &str = "overrides";
+
+#[derive(thiserror::Error, Debug)]
+pub enum FormatError {
+    #[error("loader_version must be set in pack.toml to export this pack")]
+    MissingLoaderVersion,
+    #[error("accepted_game_versions in pack.toml is empty")]
+    MissingGameVersion,
+    #[error("mod loader {0} is not supported by this format")]
+    UnsupportedLoader(String),
+    #[error("{0} is outside of the pack directory")]
+    InvalidPath(String),
+    #[error("{0} mods failed to download")]
+    DownloadFailed(usize),
+    #[error("{0} is missing a supported hash")]
+    MissingHash(String),
+    #[error("the pack does not depend on minecraft")]
+    MissingMinecraft,
+}
+
+/// The game version that exported packs target, which is the first accepted game version.
+pub fn primary_game_version(pack: &ModPack) -> Result<&str> {
+    Ok(pack.game_versions().first().ok_or(FormatError::MissingGameVersion)?)
+}
+
+/// Converts an output path into a path relative to the pack directory, rejecting any that escape it.
+pub fn relative_path(output_path: &str) -> Result<String> {
+    let path = Path::new(output_path);
+    if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(FormatError::InvalidPath(output_path.to_string()).into());
+    }
+    Ok(path.components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/"))
+}
+
+/// Makes sure every mod is downloaded and has the right hash, so that exports can read them from disk.
+pub async fn download_all(mods: &[ModMetadata]) -> Result<()> {
+    let results = Downloader::new().download_mods(mods.to_vec(), 8).await;
+    let mut failed = 0;
+    for (mod_metadata, result) in results {
+        if let Err(e) = result {
+            error(&format!("Failed to download {}: {}", mod_metadata.name, e));
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(FormatError::DownloadFailed(failed).into());
+    }
+    Ok(())
+}
+
+pub fn add_zip_file<W: Write + Seek>(zip: &mut ZipWriter<W>, path: &str, data: &[u8]) -> Result<()> {
+    zip.start_file(path, FileOptions::default())?;
+    zip.write_all(data)?;
+    Ok(())
+}
+
+/// Recursively adds the contents of `source` to the zip, under `prefix` (or at the root if it is empty).
+pub fn add_zip_dir<W: Write + Seek>(zip: &mut ZipWriter<W>, source: &Path, prefix: &str) -> Result<()> {
+    if !source.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let name = match prefix {
+            "" => entry.file_name().to_string_lossy().to_string(),
+            _ => format!("{}/{}", prefix, entry.file_name().to_string_lossy()),
+        };
+        if entry.file_type()?.is_dir() {
+            add_zip_dir(zip, &entry.path(), &name)?;
+        } else {
+            add_zip_file(zip, &name, &fs::read(entry.path())?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts every file under `prefix/` in the zip into `destination`, returning how many files were written.
+pub fn extract_zip_dir<R: Read + Seek>(zip: &mut ZipArchive<R>, prefix: &str, destination: &Path) -> Result<usize> {
+    let prefix = format!("{}/", prefix);
+    let mut count = 0;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let relative = match file.enclosed_name().and_then(|name| name.strip_prefix(&prefix).ok()) {
+            // enclosed_name still lets "overrides/../file" through, which would land outside of the destination
+            Some(relative) if relative.components().all(|c| matches!(c, Component::Normal(_))) => destination.join(relative),
+            _ => continue,
+        };
+        if file.is_dir() {
+            fs::create_dir_all(&relative)?;
+            continue;
+        }
+
+        if let Some(parent) = relative.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut file, &mut fs::File::create(&relative)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Recursively copies the contents of `source` into `destination`, returning how many files were copied.
+pub fn copy_dir(source: &Path, destination: &Path) -> Result<usize> {
+    if !source.is_dir() {
+        return Ok(0);
+    }
+    fs::create_dir_all(destination)?;
+    let mut count = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            count += copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
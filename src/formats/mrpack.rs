@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use reqwest::Url;
+use serde::{Serialize, Deserialize};
+use zip::{ZipArchive, ZipWriter};
+use crate::formats::{add_zip_dir, add_zip_file, download_all, extract_zip_dir, primary_game_version, relative_path, FormatError, OVERRIDES_DIR};
+use crate::hash::{hash_file, FileHashes, HashAlgorithm};
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata, ModSpec, ModSide, SourceKind};
+use crate::sources::modrinth::ModrinthClient;
+use crate::util::{info, warning};
+
+pub const INDEX_FILE: &str = "modrinth.index.json";
+
+/// Hosts that launchers will download mrpack files from. Anything else has to be bundled in the overrides.
+const ALLOWED_HOSTS: &[&str] = &["cdn.modrinth.com", "github.com", "raw.githubusercontent.com", "gitlab.com"];
+
+/// The hashes that every file in an mrpack index has to list.
+const REQUIRED_HASHES: [HashAlgorithm; 2] = [HashAlgorithm::Sha1, HashAlgorithm::Sha512];
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<MrpackFile>,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackFile {
+    pub path: String,
+    pub hashes: BTreeMap<String, String>,
+    pub downloads: Vec<String>,
+    pub file_size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<MrpackEnv>,
+}
+
+/// Whether a file is `required`, `optional` or `unsupported` on each side.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MrpackEnv {
+    pub client: String,
+    pub server: String,
+}
+
+impl MrpackEnv {
+    fn for_mod(side: ModSide, optional: bool) -> Option<Self> {
+        if side == ModSide::Both && !optional {
+            return None;
+        }
+        let support = |supported: bool| match (supported, optional) {
+            (false, _) => "unsupported",
+            (true, true) => "optional",
+            (true, false) => "required",
+        };
+        Some(Self {
+            client: support(side.includes(ModSide::Client)).to_string(),
+            server: support(side.includes(ModSide::Server)).to_string(),
+        })
+    }
+
+    fn is_optional(&self) -> bool {
+        self.client == "optional" || self.server == "optional"
+    }
+
+    fn side(&self) -> ModSide {
+        match (self.client.as_str(), self.server.as_str()) {
+            ("unsupported", "unsupported") => ModSide::Both,
+            ("unsupported", _) => ModSide::Server,
+            (_, "unsupported") => ModSide::Client,
+            _ => ModSide::Both,
+        }
+    }
+}
+
+/// Maps a pack.toml mod loader to its key in the mrpack dependencies block.
+pub fn loader_dependency(mod_loader: &str) -> Option<&'static str> {
+    match mod_loader {
+        "fabric" => Some("fabric-loader"),
+        "forge" => Some("forge"),
+        "quilt" => Some("quilt-loader"),
+        _ => None,
+    }
+}
+
+/// The reverse of [`loader_dependency`].
+pub fn loader_from_dependency(dependency: &str) -> Option<&'static str> {
+    match dependency {
+        "fabric-loader" => Some("fabric"),
+        "forge" => Some("forge"),
+        "quilt-loader" => Some("quilt"),
+        _ => None,
+    }
+}
+
+/// Extracts the project and version IDs from `https://cdn.modrinth.com/data/<project>/versions/<version>/<file>`.
+/// Older URLs used the version number instead of the version ID, and those are not recognised.
+pub fn parse_cdn_url(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+    if url.host_str()? != "cdn.modrinth.com" {
+        return None;
+    }
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    match segments.as_slice() {
+        ["data", project_id, "versions", version_id, _] if is_modrinth_id(version_id) => {
+            Some((project_id.to_string(), version_id.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn is_modrinth_id(id: &str) -> bool {
+    id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_allowed_url(url: &str) -> bool {
+    Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(|host| ALLOWED_HOSTS.contains(&host)))
+        .unwrap_or(false)
+}
+
+/// Writes the pack as a Modrinth modpack. Mods hosted somewhere that mrpack does not allow are bundled into the
+/// overrides if `bundle_other` is set, and are otherwise left out and reported.
+pub async fn export(pack: &ModPack, output: &Path, bundle_other: bool) -> Result<()> {
+    let mut dependencies = BTreeMap::new();
+    dependencies.insert("minecraft".to_string(), primary_game_version(pack)?.to_string());
+    let loader = loader_dependency(pack.mod_loader())
+        .ok_or_else(|| FormatError::UnsupportedLoader(pack.mod_loader().to_string()))?;
+    let loader_version = pack.loader_version().ok_or(FormatError::MissingLoaderVersion)?;
+    dependencies.insert(loader.to_string(), loader_version.to_string());
+
+    let mods = pack.get_mods();
+    download_all(&mods).await?;
+
+    let mut files = vec![];
+    let mut bundled = vec![];
+    let mut left_out = vec![];
+    for mod_metadata in mods {
+        let path = relative_path(&mod_metadata.output_path)?;
+        let downloads: Vec<String> = mod_metadata.download_urls().into_iter()
+            .filter(|url| is_allowed_url(url))
+            .map(|url| url.to_string())
+            .collect();
+        if downloads.is_empty() {
+            if bundle_other {
+                bundled.push((mod_metadata.name, path, fs::read(&mod_metadata.output_path)?));
+            } else {
+                left_out.push(mod_metadata.name);
+            }
+            continue;
+        }
+
+        // mrpack needs both of these, which mods locked by older versions of pack-it won't have
+        let mut file_hashes = mod_metadata.hashes.clone();
+        if REQUIRED_HASHES.iter().any(|algorithm| file_hashes.get(*algorithm).is_none()) {
+            file_hashes = hash_file(Path::new(&mod_metadata.output_path), &REQUIRED_HASHES)?;
+        }
+        let hashes = file_hashes.iter().map(|(name, hash)| (name.clone(), hash.clone())).collect();
+
+        files.push(MrpackFile {
+            path,
+            hashes,
+            downloads,
+            file_size: fs::metadata(&mod_metadata.output_path)?.len(),
+            env: MrpackEnv::for_mod(mod_metadata.side, pack.optional_group(&mod_metadata.name).is_some()),
+        });
+    }
+
+    let index = MrpackIndex {
+        format_version: 1,
+        game: "minecraft".to_string(),
+        version_id: pack.pack_version().to_string(),
+        name: pack.pack_name().to_string(),
+        summary: pack.author().map(|author| format!("by {}", author)),
+        files,
+        dependencies,
+    };
+
+    let mut zip = ZipWriter::new(fs::File::create(output)?);
+    add_zip_file(&mut zip, INDEX_FILE, serde_json::to_string_pretty(&index)?.as_bytes())?;
+    add_zip_dir(&mut zip, Path::new(OVERRIDES_DIR), "overrides")?;
+    for (name, path, data) in bundled {
+        info(&format!("Bundling {} into the overrides", name));
+        add_zip_file(&mut zip, &format!("overrides/{}", path), &data)?;
+    }
+    zip.finish()?;
+
+    for name in left_out {
+        warning(&format!("{} is not hosted anywhere that mrpack allows, so it was left out (use --bundle-other to include it in the overrides)", name));
+    }
+    Ok(())
+}
+
+/// Reads a Modrinth modpack into a new pack, extracting its overrides into `destination`.
+pub async fn import(file: &Path, destination: &Path) -> Result<ModPack> {
+    let mut zip = ZipArchive::new(fs::File::open(file)?)?;
+    let index: MrpackIndex = serde_json::from_reader(zip.by_name(INDEX_FILE)?)?;
+
+    let game_version = index.dependencies.get("minecraft").ok_or(FormatError::MissingMinecraft)?;
+    let (mod_loader, loader_version) = index.dependencies.iter()
+        .find_map(|(dependency, version)| loader_from_dependency(dependency).map(|loader| (loader, version.clone())))
+        .ok_or_else(|| FormatError::UnsupportedLoader("none".to_string()))?;
+
+    let mut pack = ModPack::new(index.name.clone(), None, vec![game_version.clone()], mod_loader.to_string());
+    pack.set_loader_version(Some(loader_version));
+    pack.set_pack_version(Some(index.version_id.clone()));
+
+    let mr_client = ModrinthClient::new(false)?;
+    for file in index.files {
+        let relative = relative_path(&file.path)?;
+        let output_path = format!("./{}", relative);
+        let hashes = FileHashes::from_map(&file.hashes);
+        if hashes.is_empty() {
+            return Err(FormatError::MissingHash(file.path.clone()).into());
+        }
+        let download_url = match file.downloads.first() {
+            None => {
+                warning(&format!("{} has no download URLs, skipping!", file.path));
+                continue;
+            }
+            Some(url) => url.clone(),
+        };
+        let mirror_urls = file.downloads.iter().skip(1).cloned().collect();
+        let file_stem = Path::new(&relative).file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| relative.clone());
+        let in_mods_dir = Path::new(&relative).parent() == Some(Path::new("mods"));
+        let side = file.env.as_ref().map(|env| env.side()).unwrap_or_default();
+        let optional = file.env.as_ref().is_some_and(|env| env.is_optional());
+
+        let (name, spec, update_info) = match parse_cdn_url(&download_url) {
+            Some((project_id, version_id)) => {
+                // the slug makes a much nicer name than the file name, but it is not worth failing the import over
+                let name = mr_client.get_mod(&project_id).await
+                    .map(|details| details.slug)
+                    .unwrap_or_else(|_| file_stem.clone());
+                let spec = ModSpec {
+                    output_path: if in_mods_dir { None } else { Some(output_path.clone()) },
+                    optional,
+                    ..ModSpec::new(SourceKind::Modrinth, project_id.clone(), None)
+                };
+                (name, spec, Some(ModUpdateMetadata::Modrinth { project_id, version_id, staging: None }))
+            }
+            None => {
+                let spec = ModSpec {
+                    output_path: Some(output_path.clone()),
+                    side: Some(side).filter(|side| *side != ModSide::Both),
+                    optional,
+                    ..ModSpec::new(SourceKind::Url, download_url.clone(), None)
+                };
+                (file_stem.clone(), spec, None)
+            }
+        };
+
+        let name = unique_name(&pack, name);
+        info(&format!("Imported {}", name));
+        pack.add(spec, ModMetadata {
+            name,
+            download_url,
+            mirror_urls,
+            hashes,
+            output_path,
+            side,
+            update_info,
+        });
+    }
+
+    let extracted = extract_zip_dir(&mut zip, "overrides", destination)?;
+    info(&format!("Extracted {} files from the overrides", extracted));
+    Ok(pack)
+}
+
+fn unique_name(pack: &ModPack, name: String) -> String {
+    if pack.get_spec(&name).is_none() {
+        return name;
+    }
+    (2..).map(|i| format!("{}-{}", name, i))
+        .find(|candidate| pack.get_spec(candidate).is_none())
+        .expect("ran out of names")
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::formats::{copy_dir, primary_game_version, relative_path, FormatError, OVERRIDES_DIR};
+use crate::hash::{FileHashes, HashAlgorithm};
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata, ModSpec, ModSide, SourceKind};
+use crate::sources::curseforge::{self, CurseforgeClient};
+use crate::util::{hash_from_url, info, warning};
+
+const PACK_FORMAT: &str = "packwiz:1.1.0";
+const INDEX_FILE: &str = "index.toml";
+const METAFILE_EXTENSION: &str = ".pw.toml";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizPack {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub pack_format: String,
+    pub index: PackwizIndexReference,
+    pub versions: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizIndexReference {
+    pub file: String,
+    pub hash_format: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizIndex {
+    pub hash_format: String,
+    #[serde(default)]
+    pub files: Vec<PackwizIndexFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizIndexFile {
+    pub file: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_format: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub metafile: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizMod {
+    pub name: String,
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<ModSide>,
+    pub download: PackwizDownload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<PackwizOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<PackwizUpdate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizOption {
+    pub optional: bool,
+    #[serde(default)]
+    pub default: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizDownload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub hash_format: String,
+    pub hash: String,
+    /// `metadata:curseforge` for CurseForge files, whose URL has to be looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modrinth: Option<PackwizModrinthUpdate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curseforge: Option<PackwizCurseforgeUpdate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizModrinthUpdate {
+    pub mod_id: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackwizCurseforgeUpdate {
+    pub file_id: i32,
+    pub project_id: i32,
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:02x}", Sha256::digest(data))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// Writes the pack as a packwiz pack into `output`. Every mod gets a `.pw.toml` metafile next to where it would
+/// be installed, and the contents of `overrides/` are copied in as plain files.
+pub fn export(pack: &ModPack, output: &Path) -> Result<()> {
+    let mut versions = BTreeMap::new();
+    versions.insert("minecraft".to_string(), primary_game_version(pack)?.to_string());
+    versions.insert(pack.mod_loader().to_string(), pack.loader_version().ok_or(FormatError::MissingLoaderVersion)?.to_string());
+
+    let mut index = PackwizIndex {
+        hash_format: "sha256".to_string(),
+        files: vec![],
+    };
+
+    for mod_metadata in pack.get_mods() {
+        let path = PathBuf::from(relative_path(&mod_metadata.output_path)?);
+        let filename = path.file_name().expect("output path has no file name").to_string_lossy().to_string();
+        let metafile = path.with_file_name(format!("{}{}", mod_metadata.name, METAFILE_EXTENSION));
+
+        let update = match &mod_metadata.update_info {
+            Some(ModUpdateMetadata::Modrinth { project_id, version_id, .. }) => Some(PackwizUpdate {
+                modrinth: Some(PackwizModrinthUpdate { mod_id: project_id.clone(), version: version_id.clone() }),
+                ..PackwizUpdate::default()
+            }),
+            Some(ModUpdateMetadata::Curseforge { addon_id, file_id }) => Some(PackwizUpdate {
+                curseforge: Some(PackwizCurseforgeUpdate { file_id: *file_id, project_id: *addon_id }),
+                ..PackwizUpdate::default()
+            }),
+            _ => None,
+        };
+        let (hash_format, hash) = mod_metadata.hashes.strongest()
+            .ok_or_else(|| FormatError::MissingHash(mod_metadata.name.clone()))?;
+        let packwiz_mod = PackwizMod {
+            name: mod_metadata.name.clone(),
+            filename,
+            side: Some(mod_metadata.side).filter(|side| *side != ModSide::Both),
+            download: PackwizDownload {
+                url: Some(mod_metadata.download_url.clone()),
+                hash_format: hash_format.name().to_string(),
+                hash: hash.to_string(),
+                mode: None,
+            },
+            // packwiz has no groups, so the best it can do is mention the group in the description
+            option: pack.optional_group(&mod_metadata.name).map(|group| PackwizOption {
+                optional: true,
+                default: false,
+                description: Some(group).filter(|group| *group != mod_metadata.name).map(|group| format!("Part of the {} group", group)),
+            }),
+            update,
+        };
+
+        let data = toml::to_string(&packwiz_mod)?;
+        write_file(&output.join(&metafile), data.as_bytes())?;
+        index.files.push(PackwizIndexFile {
+            file: metafile.to_string_lossy().replace('\\', "/"),
+            hash: sha256(data.as_bytes()),
+            hash_format: None,
+            metafile: true,
+        });
+    }
+
+    copy_dir(Path::new(OVERRIDES_DIR), output)?;
+    add_plain_files(&mut index, Path::new(OVERRIDES_DIR), "")?;
+
+    let index_data = toml::to_string(&index)?;
+    write_file(&output.join(INDEX_FILE), index_data.as_bytes())?;
+
+    let packwiz_pack = PackwizPack {
+        name: pack.pack_name().to_string(),
+        author: pack.author().map(|a| a.to_string()),
+        version: Some(pack.pack_version().to_string()),
+        pack_format: PACK_FORMAT.to_string(),
+        index: PackwizIndexReference {
+            file: INDEX_FILE.to_string(),
+            hash_format: "sha256".to_string(),
+            hash: sha256(index_data.as_bytes()),
+        },
+        versions,
+    };
+    write_file(&output.join("pack.toml"), toml::to_string(&packwiz_pack)?.as_bytes())?;
+    Ok(())
+}
+
+/// Lists the files under `source` in the index, as they were copied into the pack.
+fn add_plain_files(index: &mut PackwizIndex, source: &Path, prefix: &str) -> Result<()> {
+    if !source.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            add_plain_files(index, &entry.path(), &format!("{}/", name))?;
+        } else {
+            index.files.push(PackwizIndexFile {
+                file: name,
+                hash: sha256(&fs::read(entry.path())?),
+                hash_format: None,
+                metafile: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Reads a packwiz pack from `source` into a new pack, copying any plain files it contains into `destination`.
+pub async fn import(source: &Path, destination: &Path) -> Result<ModPack> {
+    let packwiz_pack: PackwizPack = toml::from_str(&fs::read_to_string(source.join("pack.toml"))?)?;
+    let index: PackwizIndex = toml::from_str(&fs::read_to_string(source.join(&packwiz_pack.index.file))?)?;
+
+    let game_version = packwiz_pack.versions.get("minecraft").ok_or(FormatError::MissingMinecraft)?;
+    let (mod_loader, loader_version) = packwiz_pack.versions.iter()
+        .find(|(loader, _)| *loader != "minecraft")
+        .ok_or_else(|| FormatError::UnsupportedLoader("none".to_string()))?;
+
+    let mut pack = ModPack::new(packwiz_pack.name.clone(), packwiz_pack.author.clone(), vec![game_version.clone()], mod_loader.clone());
+    pack.set_loader_version(Some(loader_version.clone()));
+    pack.set_pack_version(packwiz_pack.version.clone());
+
+    let mut cf_client = None;
+    let mut copied = 0;
+    // the index lives next to pack.toml, and file paths are relative to it
+    let index_dir = source.join(&packwiz_pack.index.file).parent().map(|p| p.to_path_buf()).unwrap_or_else(|| source.to_path_buf());
+    for file in index.files {
+        let path = relative_path(&file.file)?;
+        if !file.metafile {
+            write_file(&destination.join(&path), &fs::read(index_dir.join(&path))?)?;
+            copied += 1;
+            continue;
+        }
+
+        let packwiz_mod: PackwizMod = toml::from_str(&fs::read_to_string(index_dir.join(&path))?)?;
+        let name = path.rsplit('/').next().unwrap_or(&path).trim_end_matches(METAFILE_EXTENSION).to_string();
+        let output_path = match Path::new(&path).parent().map(|p| p.to_string_lossy().to_string()) {
+            Some(parent) if !parent.is_empty() => format!("./{}/{}", parent, packwiz_mod.filename),
+            _ => format!("./{}", packwiz_mod.filename),
+        };
+
+        let update = packwiz_mod.update.unwrap_or_default();
+        let update_info = match (update.modrinth, update.curseforge) {
+            (Some(modrinth), _) => Some(ModUpdateMetadata::Modrinth { project_id: modrinth.mod_id, version_id: modrinth.version, staging: None }),
+            (None, Some(curseforge)) => Some(ModUpdateMetadata::Curseforge { addon_id: curseforge.project_id, file_id: curseforge.file_id }),
+            (None, None) => None,
+        };
+
+        let download_url = match (&packwiz_mod.download.url, &update_info) {
+            (Some(url), _) => url.clone(),
+            (None, Some(ModUpdateMetadata::Curseforge { addon_id, file_id })) => {
+                if cf_client.is_none() {
+                    cf_client = Some(CurseforgeClient::new()?);
+                }
+                let mod_data = cf_client.as_ref().expect("client was just created").find_mod_by_id(*addon_id).await?;
+                match mod_data.files.into_iter().find(|f| f.id == *file_id) {
+                    Some(version) => version.download_url,
+                    None => {
+                        warning(&format!("File {} of {} could not be found, skipping!", file_id, name));
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                warning(&format!("{} has no download URL, skipping!", name));
+                continue;
+            }
+        };
+
+        // packwiz also allows md5 and murmur2, which pack-it can't verify with, so those have to be recomputed
+        let hashes = match HashAlgorithm::from_name(&packwiz_mod.download.hash_format) {
+            Some(algorithm) => {
+                let mut hashes = FileHashes::default();
+                hashes.insert(algorithm, packwiz_mod.download.hash.to_lowercase());
+                hashes
+            }
+            None => {
+                info(&format!("Hashing {}...", packwiz_mod.filename));
+                hash_from_url(&download_url).await?
+            }
+        };
+
+        let side = packwiz_mod.side.unwrap_or_default();
+        let in_mods_dir = output_path.starts_with("./mods/") && !output_path["./mods/".len()..].contains('/');
+        let spec = match &update_info {
+            Some(ModUpdateMetadata::Modrinth { project_id, .. }) => ModSpec::new(SourceKind::Modrinth, project_id.clone(), None),
+            Some(ModUpdateMetadata::Curseforge { addon_id, .. }) => ModSpec::new(SourceKind::Curseforge, addon_id.to_string(), None),
+            _ => ModSpec::new(SourceKind::Url, download_url.clone(), None),
+        };
+        let spec = ModSpec {
+            output_path: if in_mods_dir && spec.source != SourceKind::Url { None } else { Some(output_path.clone()) },
+            // Modrinth reports the side itself, but other sources need to remember it
+            side: Some(side).filter(|side| *side != ModSide::Both && spec.source != SourceKind::Modrinth),
+            optional: packwiz_mod.option.as_ref().is_some_and(|option| option.optional),
+            ..spec
+        };
+
+        info(&format!("Imported {}", name));
+        pack.add(spec, ModMetadata {
+            name,
+            mirror_urls: curseforge::mirror_urls(&download_url),
+            download_url,
+            hashes,
+            output_path,
+            side,
+            update_info,
+        });
+    }
+
+    info(&format!("Copied {} files from the pack", copied));
+    Ok(pack)
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use serde::Serialize;
+use zip::ZipWriter;
+use crate::formats::{add_zip_dir, copy_dir, download_all, primary_game_version, relative_path, FormatError, OVERRIDES_DIR};
+use crate::pack::{ModPack, ModMetadata, ModSide};
+
+const INSTANCE_FILE: &str = "instance.cfg";
+const COMPONENTS_FILE: &str = "mmc-pack.json";
+const GAME_DIR: &str = ".minecraft";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MmcPack {
+    components: Vec<MmcComponent>,
+    format_version: i32,
+}
+
+#[derive(Serialize, Debug)]
+struct MmcComponent {
+    uid: String,
+    version: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    important: bool,
+}
+
+impl MmcComponent {
+    fn new(uid: &str, version: &str) -> Self {
+        Self {
+            uid: uid.to_string(),
+            version: version.to_string(),
+            important: false,
+        }
+    }
+}
+
+/// The launcher components for the pack's mod loader. Fabric and Quilt also need the intermediary mappings for the
+/// game version.
+fn loader_components(mod_loader: &str, loader_version: &str, game_version: &str) -> Option<Vec<MmcComponent>> {
+    let components = match mod_loader {
+        "fabric" => vec![
+            MmcComponent::new("net.fabricmc.intermediary", game_version),
+            MmcComponent::new("net.fabricmc.fabric-loader", loader_version),
+        ],
+        "quilt" => vec![
+            MmcComponent::new("net.fabricmc.intermediary", game_version),
+            MmcComponent::new("org.quiltmc.quilt-loader", loader_version),
+        ],
+        "forge" => vec![MmcComponent::new("net.minecraftforge", loader_version)],
+        _ => return None,
+    };
+    Some(components)
+}
+
+/// Writes the pack as a Prism Launcher (or MultiMC) instance. If `output` ends in `.zip` the instance is zipped
+/// up so that it can be imported directly, otherwise it is written into the `output` directory.
+pub async fn export(pack: &ModPack, output: &Path) -> Result<()> {
+    if output.extension().is_some_and(|extension| extension == "zip") {
+        let instance = tempfile::tempdir()?;
+        write_instance(pack, instance.path()).await?;
+
+        let mut zip = ZipWriter::new(fs::File::create(output)?);
+        add_zip_dir(&mut zip, instance.path(), "")?;
+        zip.finish()?;
+        Ok(())
+    } else {
+        write_instance(pack, output).await
+    }
+}
+
+async fn write_instance(pack: &ModPack, output: &Path) -> Result<()> {
+    let game_version = primary_game_version(pack)?;
+    let loader_version = pack.loader_version().ok_or(FormatError::MissingLoaderVersion)?;
+    let mut components = vec![MmcComponent {
+        important: true,
+        ..MmcComponent::new("net.minecraft", game_version)
+    }];
+    components.extend(loader_components(pack.mod_loader(), loader_version, game_version)
+        .ok_or_else(|| FormatError::UnsupportedLoader(pack.mod_loader().to_string()))?);
+
+    fs::create_dir_all(output)?;
+    let mmc_pack = MmcPack {
+        components,
+        format_version: 1,
+    };
+    fs::write(output.join(COMPONENTS_FILE), serde_json::to_string_pretty(&mmc_pack)?)?;
+    fs::write(output.join(INSTANCE_FILE), format!("InstanceType=OneSix\nname={}\n", pack.pack_name()))?;
+
+    let game_dir = output.join(GAME_DIR);
+    copy_dir(Path::new(OVERRIDES_DIR), &game_dir)?;
+
+    // download straight into the instance, rather than copying from the pack directory
+    let mods = pack.get_mods().into_iter()
+        .filter(|mod_metadata| mod_metadata.side.includes(ModSide::Client))
+        .map(|mod_metadata| Ok(ModMetadata {
+            output_path: game_dir.join(relative_path(&mod_metadata.output_path)?).to_string_lossy().to_string(),
+            ..mod_metadata
+        }))
+        .collect::<Result<Vec<ModMetadata>>>()?;
+    download_all(&mods).await
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use serde::{Serialize, Deserialize, Deserializer};
+use sha2::digest::DynDigest;
+
+/// The hash algorithms pack-it understands, ordered from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 3] = [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+
+    /// The name of the algorithm, as used in pack.lock and by Modrinth, packwiz and mrpack.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|algorithm| algorithm.name() == name)
+    }
+
+    fn digest(&self) -> Box<dyn DynDigest> {
+        match self {
+            HashAlgorithm::Sha1 => Box::new(sha1::Sha1::default()),
+            HashAlgorithm::Sha256 => Box::new(sha2::Sha256::default()),
+            HashAlgorithm::Sha512 => Box::new(sha2::Sha512::default()),
+        }
+    }
+}
+
+/// The hex digests of a file, keyed by algorithm name.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct FileHashes(BTreeMap<String, String>);
+
+impl FileHashes {
+    /// Keeps the hashes in `hashes` that use a known algorithm, ignoring any others.
+    pub fn from_map<'a>(hashes: impl IntoIterator<Item = (&'a String, &'a String)>) -> Self {
+        Self(hashes.into_iter()
+            .filter(|(name, _)| HashAlgorithm::from_name(name).is_some())
+            .map(|(name, hash)| (name.clone(), hash.to_lowercase()))
+            .collect())
+    }
+
+    pub fn get(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        self.0.get(algorithm.name()).map(|hash| hash.as_str())
+    }
+
+    pub fn insert(&mut self, algorithm: HashAlgorithm, hash: String) {
+        self.0.insert(algorithm.name().to_string(), hash);
+    }
+
+    /// Adds the hashes from `other` that are not already known.
+    pub fn merge(&mut self, other: FileHashes) {
+        for (name, hash) in other.0 {
+            self.0.entry(name).or_insert(hash);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The strongest hash available, which is the one downloads are verified with.
+    pub fn strongest(&self) -> Option<(HashAlgorithm, &str)> {
+        HashAlgorithm::ALL.iter().rev()
+            .find_map(|algorithm| self.get(*algorithm).map(|hash| (*algorithm, hash)))
+    }
+
+    /// Checks whether two sets of hashes are for the same file, going by the strongest hash they both have.
+    pub fn matches(&self, other: &FileHashes) -> bool {
+        HashAlgorithm::ALL.iter().rev()
+            .find_map(|algorithm| Some((self.get(*algorithm)?, other.get(*algorithm)?)))
+            .is_some_and(|(hash, other_hash)| hash == other_hash)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+impl<'de> Deserialize<'de> for FileHashes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Older pack.lock files (and installed_mods) stored a single SHA-1 hash in download_hash
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum StoredHashes {
+            Sha1(String),
+            Map(BTreeMap<String, String>),
+        }
+
+        Ok(match StoredHashes::deserialize(deserializer)? {
+            StoredHashes::Sha1(hash) => {
+                let mut hashes = FileHashes::default();
+                hashes.insert(HashAlgorithm::Sha1, hash);
+                hashes
+            }
+            StoredHashes::Map(hashes) => FileHashes(hashes),
+        })
+    }
+}
+
+/// Computes several hashes of the same data in one pass.
+pub struct FileHasher {
+    digests: Vec<(HashAlgorithm, Box<dyn DynDigest>)>,
+}
+
+impl FileHasher {
+    pub fn new(algorithms: &[HashAlgorithm]) -> Self {
+        Self {
+            digests: algorithms.iter().map(|algorithm| (*algorithm, algorithm.digest())).collect(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, digest) in &mut self.digests {
+            digest.update(data);
+        }
+    }
+
+    pub fn finish(self) -> FileHashes {
+        let mut hashes = FileHashes::default();
+        for (algorithm, digest) in self.digests {
+            let hash = digest.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+            hashes.insert(algorithm, hash);
+        }
+        hashes
+    }
+}
+
+impl Write for FileHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn hash_file(path: &Path, algorithms: &[HashAlgorithm]) -> io::Result<FileHashes> {
+    let mut hasher = FileHasher::new(algorithms);
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finish())
+}
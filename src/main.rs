@@ -1,17 +1,40 @@
-use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata};
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata, ModSpec, ModSide, SourceKind};
 use structopt::StructOpt;
-use std::path::Path;
-use crate::util::{error, complete, warning, hash_from_url, info};
-use crate::sources::curseforge::CurseforgeClient;
-use crate::sources::modrinth::ModrinthClient;
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use crate::util::{error, complete, warning, info};
+use crate::sources::curseforge::{CurseforgeClient, CurseforgeAddon, CurseforgeModFile};
+use crate::sources::modrinth::{self, ModrinthClient, ResolvedModrinthVersion};
 use crate::sources::github::{GithubClient, get_github_token};
-use dialoguer::{Input, Select};
+use crate::sources::url::UrlSource;
+use crate::sources::maven::MavenCoordinate;
+use crate::sources::gitlab::GITLAB_HOST;
+use crate::sources::gitea::GITEA_HOST;
+use crate::update::{apply_update, Updater, UpdateCheck};
+use crate::resolve::Resolver;
+use crate::hash::{hash_file, HashAlgorithm};
+use crate::state::{normalize_path, InstallState};
+use crate::cache::Cache;
+use crate::download::DownloadOptions;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
 use dialoguer::theme::ColorfulTheme;
+use colored::Colorize;
 
 mod pack;
 mod util;
 mod sources;
 mod download;
+mod update;
+mod resolve;
+mod state;
+mod prune;
+mod formats;
+mod hash;
+mod cache;
+#[cfg(test)]
+mod test_server;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "pack-it")]
@@ -23,6 +46,8 @@ enum Opt {
 
     #[structopt(help = "add a mod from CurseForge to the pack")]
     CurseforgeAdd {
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
         mod_identifiers: Vec<String>,
     },
 
@@ -30,6 +55,8 @@ enum Opt {
     ModrinthAdd {
         #[structopt(long, short, help = "use the staging instance of the modrinth api")]
         staging: bool,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
         mod_identifiers: Vec<String>,
     },
 
@@ -37,11 +64,109 @@ enum Opt {
     GithubAdd {
         owner: String,
         repo: String,
-        tag: String,
+        #[structopt(help = "the release tag to pin the mod to, the latest release is used if omitted")]
+        tag: Option<String>,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
     },
 
-    #[structopt(help = "Download all mods specified in pack.toml")]
-    DownloadMods,
+    #[structopt(help = "add a mod from a GitLab release to the pack")]
+    GitlabAdd {
+        #[structopt(help = "the path of the project, eg. group/project")]
+        project: String,
+        #[structopt(help = "the release tag to pin the mod to, the latest release is used if omitted")]
+        tag: Option<String>,
+        #[structopt(long, default_value = GITLAB_HOST, help = "the GitLab instance the project is on")]
+        host: String,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
+    },
+
+    #[structopt(help = "add a mod from a Gitea or Forgejo release to the pack")]
+    GiteaAdd {
+        owner: String,
+        repo: String,
+        #[structopt(help = "the release tag to pin the mod to, the latest release is used if omitted")]
+        tag: Option<String>,
+        #[structopt(long, default_value = GITEA_HOST, help = "the Gitea or Forgejo instance the repository is on")]
+        host: String,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
+    },
+
+    #[structopt(help = "add a file from any URL to the pack")]
+    UrlAdd {
+        url: String,
+        #[structopt(long, help = "the name of the mod in pack.toml, defaults to the name of the file")]
+        name: Option<String>,
+        #[structopt(long, help = "where to save the file, defaults to ./mods/<file name>")]
+        output_path: Option<String>,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
+    },
+
+    #[structopt(help = "add an artifact from a Maven repository to the pack")]
+    MavenAdd {
+        #[structopt(help = "the URL of the repository, eg. https://maven.fabricmc.net")]
+        repository: String,
+        #[structopt(help = "group:artifact:version[:classifier], where version can be + for the latest version or a range like [1.0,2.0)")]
+        coordinate: MavenCoordinate,
+        #[structopt(long, help = "fail straight away instead of using the network")]
+        offline: bool,
+    },
+
+    #[structopt(help = "update mods in the pack to their latest compatible versions")]
+    Update {
+        mods: Vec<String>,
+    },
+
+    #[structopt(help = "list mods that have a newer compatible version, without updating them")]
+    Outdated {
+        mods: Vec<String>,
+    },
+
+    #[structopt(help = "show details about a mod in the pack")]
+    Info {
+        mod_name: String,
+    },
+
+    #[structopt(help = "resolve the mods in pack.toml and write pack.lock")]
+    Lock,
+
+    #[structopt(help = "Download all mods specified in pack.lock")]
+    DownloadMods {
+        #[structopt(long, short, default_value = "8", help = "the number of mods to download at once")]
+        jobs: usize,
+        #[structopt(long, help = "remove files installed by pack-it that are no longer in the pack")]
+        prune: bool,
+        #[structopt(long, default_value = "both", help = "only download mods needed on this side (client, server or both)")]
+        side: ModSide,
+        #[structopt(long, help = "install the optional mods in this group")]
+        with: Vec<String>,
+        #[structopt(long, help = "don't install the optional mods in this group")]
+        without: Vec<String>,
+        #[structopt(long, help = "only install mods that are already downloaded or in the download cache")]
+        offline: bool,
+        #[structopt(long, default_value = "3", help = "how many times to retry a failed download")]
+        retries: u32,
+        #[structopt(long, default_value = "30", help = "seconds to wait for a server to respond before retrying")]
+        timeout: u64,
+    },
+
+    #[structopt(help = "manage the download cache shared between packs")]
+    Cache(CacheCommand),
+
+    #[structopt(help = "remove files installed by pack-it that are no longer in the pack")]
+    Prune {
+        #[structopt(long, help = "also remove files that were not installed by pack-it")]
+        all: bool,
+    },
+
+    #[structopt(help = "export the pack to another format")]
+    Export(ExportFormat),
+
+    #[structopt(help = "create a pack.toml from a pack in another format")]
+    Import(ImportFormat),
 
     #[structopt(help = "remove mods from the pack")]
     Remove {
@@ -54,6 +179,60 @@ enum Opt {
     // MRTest,
 }
 
+#[derive(StructOpt, Debug)]
+enum CacheCommand {
+    #[structopt(help = "remove old or unused files from the cache")]
+    Gc {
+        #[structopt(long, default_value = "30", help = "remove files that have not been used for this many days")]
+        max_age: u64,
+        #[structopt(long, help = "also remove files that are not used by any pack on this machine")]
+        unused: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum ExportFormat {
+    #[structopt(help = "export the pack as a Modrinth modpack (.mrpack)")]
+    Mrpack {
+        #[structopt(long, short, help = "the file to write, defaults to <pack name>.mrpack")]
+        output: Option<PathBuf>,
+        #[structopt(long, help = "bundle mods that are not hosted on an allowed site into the overrides")]
+        bundle_other: bool,
+    },
+    #[structopt(help = "export the pack as a CurseForge modpack zip")]
+    Curseforge {
+        #[structopt(long, short, help = "the file to write, defaults to <pack name>.zip")]
+        output: Option<PathBuf>,
+        #[structopt(long, help = "bundle mods that are not from CurseForge into overrides/mods")]
+        bundle_other: bool,
+    },
+    #[structopt(help = "export the pack as a packwiz pack")]
+    Packwiz {
+        directory: PathBuf,
+    },
+    #[structopt(help = "export the pack as a Prism Launcher / MultiMC instance")]
+    Prism {
+        #[structopt(help = "the instance directory, or a .zip file to create")]
+        output: PathBuf,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum ImportFormat {
+    #[structopt(help = "import a Modrinth modpack (.mrpack)")]
+    Mrpack {
+        file: PathBuf,
+    },
+    #[structopt(help = "import a CurseForge modpack zip or manifest.json")]
+    Curseforge {
+        file: PathBuf,
+    },
+    #[structopt(help = "import a packwiz pack from a directory")]
+    Packwiz {
+        directory: PathBuf,
+    },
+}
+
 async fn init_pack() -> anyhow::Result<()> {
     let pack_file_path = Path::new("pack.toml");
     if pack_file_path.exists() {
@@ -78,9 +257,9 @@ async fn init_pack() -> anyhow::Result<()> {
         .items(&mod_loaders[..])
         .interact()?;
     let mod_loader = mod_loaders[mod_loader];
-    let supported_game_versions: Vec<&str> = supported_game_versions.split(|c| c == ' ' || c == ',').collect();
+    let supported_game_versions: Vec<&str> = supported_game_versions.split([' ', ',']).collect();
     let supported_game_versions = supported_game_versions.iter().map(|s| s.to_string()).collect();
-    let pack = ModPack::new(pack_name, pack_author, supported_game_versions, mod_loader.to_lowercase().to_string());
+    let pack = ModPack::new(pack_name, Some(pack_author), supported_game_versions, mod_loader.to_lowercase().to_string());
 
     if pack_file_path.exists() {
         error("A pack.toml file already exists in this directory!");
@@ -92,9 +271,208 @@ async fn init_pack() -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn curseforge_mod_metadata(mod_data: &CurseforgeAddon, version: CurseforgeModFile) -> anyhow::Result<ModMetadata> {
+    mod_data.resolved(version).into_metadata().await
+}
+
+/// Adds a CurseForge mod along with its required dependencies.
+/// Nothing is added if a dependency cannot be satisfied or an incompatible mod is present.
+async fn add_curseforge_mod(pack: &mut ModPack, cf_client: &CurseforgeClient, mod_data: CurseforgeAddon, version: CurseforgeModFile) -> anyhow::Result<()> {
+    let dependencies = cf_client.resolve_dependencies(&mod_data, &version, &|files| pack.resolve_curseforge_version(files),
+                                                      &|id| pack.has_curseforge_addon(id)).await?;
+
+    for dependency in &dependencies.incompatible {
+        error(&format!("{} is incompatible with {}!", dependency.addon.name, dependency.required_by));
+    }
+    for dependency in &dependencies.unresolved {
+        error(&format!("No compatible version found for {}, which is required by {}!", dependency.addon.name, dependency.required_by));
+    }
+    if !dependencies.incompatible.is_empty() || !dependencies.unresolved.is_empty() {
+        error(&format!("Not adding {} to the pack!", mod_data.name));
+        return Ok(());
+    }
+
+    for dependency in &dependencies.optional {
+        info(&format!("{} optionally depends on {}, use `pack-it curseforge-add {}` to add it", dependency.required_by, dependency.addon.name, dependency.addon.slug));
+    }
+
+    let mut to_add = vec![curseforge_mod_metadata(&mod_data, version).await?];
+    for dependency in dependencies.required {
+        let file = dependency.file.expect("Required dependency was resolved without a file");
+        to_add.push(curseforge_mod_metadata(&dependency.addon, file).await?);
+        info(&format!("Pulled in {} by {} (required by {})", dependency.addon.name, dependency.addon.format_authors(), dependency.required_by));
+    }
+
+    for mod_metadata in to_add {
+        pack.add(ModSpec::new(SourceKind::Curseforge, mod_metadata.name.clone(), None), mod_metadata);
+    }
+    complete(&format!("Added {} by {} to the pack!", mod_data.name, mod_data.format_authors()));
+    Ok(())
+}
+
+async fn modrinth_mod_metadata(staging: bool, resolved: ResolvedModrinthVersion) -> anyhow::Result<ModMetadata> {
+    modrinth::resolved_mod(if staging { Some(true) } else { None }, resolved).into_metadata().await
+}
+
+/// Adds a resolved Modrinth mod along with its dependencies, prompting for any optional ones.
+/// Nothing is added if a dependency cannot be satisfied or an incompatible mod is present.
+async fn add_modrinth_mod(pack: &mut ModPack, mr_client: &ModrinthClient, staging: bool, resolved: ResolvedModrinthVersion,
+                          pinned_version: Option<String>) -> anyhow::Result<()> {
+    let title = resolved.0.title.clone();
+    let mut pending = vec![resolved.1.clone()];
+    let mut to_add = vec![resolved];
+    let mut incompatible = vec![];
+    let mut unresolved = vec![];
+    let interactive = std::io::stdin().is_terminal();
+
+    while !pending.is_empty() {
+        let dependencies = mr_client.resolve_dependencies(&pending, &|v| pack.supports(&v), &|id| {
+            pack.has_modrinth_project(id) || to_add.iter().any(|(m, _, _)| m.id == id)
+        }).await?;
+        pending = vec![];
+
+        for dependency in dependencies.required {
+            info(&format!("Adding required dependency {}...", dependency.0.title));
+            pending.push(dependency.1.clone());
+            to_add.push(dependency);
+        }
+        for dependency in dependencies.optional {
+            if !interactive {
+                info(&format!("{} is an optional dependency, use `pack-it modrinth-add {}` to add it", dependency.0.title, dependency.0.slug));
+                continue;
+            }
+            let wanted = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("{} is an optional dependency, add it to the pack?", dependency.0.title))
+                .default(false)
+                .interact()?;
+            if wanted {
+                pending.push(dependency.1.clone());
+                to_add.push(dependency);
+            }
+        }
+        incompatible.extend(dependencies.incompatible);
+        unresolved.extend(dependencies.unresolved);
+    }
+
+    for mod_data in &incompatible {
+        error(&format!("{} is incompatible with {}!", mod_data.title, title));
+    }
+    for mod_data in &unresolved {
+        error(&format!("No compatible version found for required dependency {}!", mod_data.title));
+    }
+    if !incompatible.is_empty() || !unresolved.is_empty() {
+        error(&format!("Not adding {} to the pack!", title));
+        return Ok(());
+    }
+
+    let mut pinned_version = pinned_version;
+    for resolved in to_add {
+        let mod_title = resolved.0.title.clone();
+        let spec = ModSpec {
+            staging: if staging { Some(true) } else { None },
+            // only the mod that was asked for can be pinned, dependencies always start out unpinned
+            ..ModSpec::new(SourceKind::Modrinth, resolved.0.slug.clone(), pinned_version.take())
+        };
+        pack.add(spec, modrinth_mod_metadata(staging, resolved).await?);
+        complete(&format!("Added {} to the pack!", mod_title));
+    }
+    Ok(())
+}
+
+/// The locked mods with the given names, or every locked mod if no names are given.
+fn select_mods(pack: &ModPack, mods: Vec<String>) -> Vec<ModMetadata> {
+    if mods.is_empty() {
+        return pack.get_mods();
+    }
+
+    let mut targets = vec![];
+    for mod_name in mods {
+        match pack.get_mod(&mod_name) {
+            None => error(&format!("No mod in pack called {}!", mod_name)),
+            Some(mod_metadata) => targets.push(mod_metadata.clone()),
+        }
+    }
+    targets
+}
+
+/// Creates an updater, only setting up GitHub if one of the mods needs it.
+fn updater_for(mods: &[ModMetadata]) -> anyhow::Result<Updater> {
+    let needs_github = mods.iter()
+        .any(|m| matches!(m.update_info, Some(ModUpdateMetadata::GitHub { .. })));
+    Updater::new(if needs_github { Some(GithubClient::new(get_github_token())?) } else { None })
+}
+
+fn print_field(label: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        println!("  {}: {}", label.bold(), value);
+    }
+}
+
+/// Adds a mod from a release on GitLab or Gitea, which have no dependencies to resolve.
+async fn add_release_mod(name: &str, spec: ModSpec) -> anyhow::Result<()> {
+    let pack_path = std::path::Path::new("pack.toml");
+    let mut pack = ModPack::load(pack_path)?;
+    info(&format!("Resolving {}...", spec.id));
+
+    let resolver = Resolver::new(None)?;
+    match resolver.resolve(&pack, name, &spec).await? {
+        None => warning(&format!("No valid file found for {}", spec.id)),
+        Some(mod_metadata) => {
+            let version = mod_metadata.update_info.as_ref().map(|update_info| update_info.version()).unwrap_or_default();
+            pack.add(spec, mod_metadata);
+            pack.save(pack_path)?;
+            complete(&format!("Added {} {} to the pack!", name, version));
+        }
+    }
+    Ok(())
+}
+
+/// Reports any mods that are missing from pack.lock, returning whether the lock is up to date.
+fn ensure_locked(pack: &ModPack) -> bool {
+    let unlocked = pack.unlocked_mods();
+    for (name, _) in &unlocked {
+        error(&format!("{} is not locked!", name));
+    }
+    if !unlocked.is_empty() {
+        error("pack.lock is out of date, run `pack-it lock` first!");
+    }
+    unlocked.is_empty()
+}
+
+/// Works out which groups of optional mods to install. Groups that were not given with `--with` or `--without`
+/// are asked about interactively, or left out if there is nobody to ask.
+fn choose_optional_groups(pack: &ModPack, with: &[String], without: &[String]) -> anyhow::Result<BTreeSet<String>> {
+    let groups = pack.optional_groups();
+    for group in with.iter().chain(without) {
+        if !groups.contains(group.as_str()) {
+            warning(&format!("There is no group of optional mods called {}!", group));
+        }
+    }
+
+    let mut chosen: BTreeSet<String> = with.iter().cloned().collect();
+    let undecided: Vec<&str> = groups.into_iter()
+        .filter(|group| !with.iter().chain(without).any(|g| g == group))
+        .collect();
+    if !undecided.is_empty() && std::io::stdin().is_terminal() {
+        let selected = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("Which optional mods do you want to install? (space to select)")
+            .items(&undecided)
+            .interact()?;
+        chosen.extend(selected.into_iter().map(|i| undecided[i].to_string()));
+    }
+    Ok(chosen)
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let opt: Opt = Opt::from_args();
+    let offline = match &opt {
+        Opt::CurseforgeAdd { offline, .. } | Opt::ModrinthAdd { offline, .. }
+        | Opt::GithubAdd { offline, .. } | Opt::GitlabAdd { offline, .. } | Opt::GiteaAdd { offline, .. }
+        | Opt::UrlAdd { offline, .. } | Opt::MavenAdd { offline, .. } | Opt::DownloadMods { offline, .. } => *offline,
+        _ => false,
+    };
+    util::set_offline(offline || std::env::var_os("PACK_IT_OFFLINE").is_some());
 
     match opt {
         Opt::Init => {
@@ -124,127 +502,413 @@ async fn main() -> anyhow::Result<()> {
         //     println!("{:?}", mod_version);
         // }
 
-        Opt::CurseforgeAdd { mod_identifiers } => {
+        Opt::CurseforgeAdd { mod_identifiers, .. } => {
             let pack_path = std::path::Path::new("pack.toml");
-            let mut pack = ModPack::read(&mut std::fs::File::open(pack_path)?)?;
+            let mut pack = ModPack::load(pack_path)?;
             let cf_client = CurseforgeClient::new()?;
             for mod_slug in mod_identifiers {
-                info(&*(format!("Resolving {}...", mod_slug)));
+                info(&format!("Resolving {}...", mod_slug));
                 let mod_data = cf_client.find_mod_by_slug(&mod_slug).await?;
                 let version = pack.resolve_curseforge_version(&mod_data.files);
                 if let Some(version) = version {
-                    info(&*(format!("Hashing {}...", version.file_name)));
-                    let hash = hash_from_url(&version.download_url).await?;
-                    pack.add(ModMetadata {
-                        name: mod_data.slug.clone(),
-                        output_path: format!("./mods/{}", version.file_name),
-                        download_url: version.download_url,
-                        download_hash: hash,
-                        update_info: Some(ModUpdateMetadata::Curseforge {
-                            addon_id: mod_data.id,
-                            file_id: version.id,
-                        })
-                    });
-                    pack.save(&pack_path)?;
-                    complete(&*format!("Added {} by {} to the pack!", mod_data.name, mod_data.format_authors()))
+                    add_curseforge_mod(&mut pack, &cf_client, mod_data, version).await?;
+                    pack.save(pack_path)?;
                 } else {
-                    warning(&*format!("No compatible version found for {}!", mod_slug))
+                    warning(&format!("No compatible version found for {}!", mod_slug))
                 }
             }
         }
 
-        Opt::ModrinthAdd { staging, mod_identifiers } => {
+        Opt::ModrinthAdd { staging, mod_identifiers, .. } => {
             let pack_path = std::path::Path::new("pack.toml");
-            let mut pack = ModPack::read(&mut std::fs::File::open(pack_path)?)?;
+            let mut pack = ModPack::load(pack_path)?;
             let mr_client = ModrinthClient::new(staging)?;
             for mod_id in mod_identifiers {
-                info(&*(format!("Resolving {}...", mod_id)));
+                info(&format!("Resolving {}...", mod_id));
                 let version = mr_client.resolve_mod(&mod_id, &|v| pack.supports(&v)).await?;
-                if let Some((mod_data, version, file)) = version {
-                    let update_metadata = if staging {
-                        ModUpdateMetadata::Modrinth {
-                            project_id: version.mod_id.clone(),
-                            version_id: version.id,
-                            staging: Some(true)
-                        }
-                    } else {
-                        ModUpdateMetadata::Modrinth {
-                            project_id: version.mod_id.clone(),
-                            version_id: version.id,
-                            staging: None
-                        }
-                    };
-
-                    pack.add(ModMetadata {
-                        name: mod_data.slug.clone(),
-                        output_path: format!("./mods/{}", file.filename),
-                        download_url: file.url,
-                        download_hash: file.hashes.get("sha1").expect("Modrinth did not supply a sha1 hash").clone(),
-                        update_info: Some(update_metadata)
-                    });
-                    pack.save(&pack_path)?;
-                    complete(&*format!("Added {} to the pack!", mod_data.title))
+                if let Some(resolved) = version {
+                    let pinned_version = mod_id.split_once(':').map(|(_, version)| version.to_string());
+                    add_modrinth_mod(&mut pack, &mr_client, staging, resolved, pinned_version).await?;
+                    pack.save(pack_path)?;
                 } else {
-                    warning(&*format!("No compatible version found for {}!", mod_id))
+                    warning(&format!("No compatible version found for {}!", mod_id))
                 }
             }
         }
 
-        Opt::GithubAdd { owner, repo, tag } => {
+        Opt::GithubAdd { owner, repo, tag, .. } => {
             let pack_path = std::path::Path::new("pack.toml");
-            let mut pack = ModPack::read(&mut std::fs::File::open(pack_path)?)?;
-
-            info(&*format!("Resolving {}/{}:{}...", owner, repo, tag));
-
-            match GithubClient::new(get_github_token()).resolve_mod(&*owner, &*repo, &*tag).await? {
-                None => warning(&*format!("No valid file found for {}/{}:{}", owner, repo, tag)),
-                Some(asset) => {
-                    info(&*format!("Hashing {}...", asset.name));
-                    let hash = hash_from_url(&asset.browser_download_url.to_string()).await?;
-
-                    pack.add(ModMetadata {
-                        name: repo.clone(),
-                        output_path: format!("./mods/{}", asset.name),
-                        download_url: asset.browser_download_url.to_string(),
-                        download_hash: hash,
-                        update_info: Some(ModUpdateMetadata::GitHub {
-                            owner,
-                            repo,
-                            tag,
-                        })
-                    });
-                    pack.save(&pack_path)?;
-
-                    complete(asset.browser_download_url.as_str())
+            let mut pack = ModPack::load(pack_path)?;
+
+            let spec = ModSpec::new(SourceKind::GitHub, format!("{}/{}", owner, repo), tag);
+            info(&format!("Resolving {}...", spec.id));
+
+            let resolver = Resolver::new(Some(GithubClient::new(get_github_token())?))?;
+            match resolver.resolve(&pack, &repo, &spec).await? {
+                None => warning(&format!("No valid file found for {}", spec.id)),
+                Some(mod_metadata) => {
+                    let download_url = mod_metadata.download_url.clone();
+                    pack.add(spec, mod_metadata);
+                    pack.save(pack_path)?;
+
+                    complete(&download_url)
                 },
             }
         }
 
-        Opt::DownloadMods { } => {
+        Opt::GitlabAdd { project, tag, host, .. } => {
+            let spec = ModSpec {
+                host: Some(host).filter(|host| host != GITLAB_HOST),
+                ..ModSpec::new(SourceKind::GitLab, project.trim_matches('/').to_string(), tag)
+            };
+            let name = spec.id.rsplit('/').next().unwrap_or_default().to_string();
+            add_release_mod(&name, spec).await?;
+        }
+
+        Opt::GiteaAdd { owner, repo, tag, host, .. } => {
+            let spec = ModSpec {
+                host: Some(host).filter(|host| host != GITEA_HOST),
+                ..ModSpec::new(SourceKind::Gitea, format!("{}/{}", owner, repo), tag)
+            };
+            add_release_mod(&repo, spec).await?;
+        }
+
+        Opt::UrlAdd { url, name, output_path, .. } => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let mut pack = ModPack::load(pack_path)?;
+
+            let spec = ModSpec {
+                output_path,
+                ..ModSpec::new(SourceKind::Url, url, None)
+            };
+            info(&format!("Resolving {}...", spec.id));
+            let source = UrlSource::new()?;
+            // only the headers have arrived at this point, so nothing is downloaded if the mod can't be added
+            let (resolved, response) = source.request(&spec.id, None, None).await?;
+
+            let name = name.unwrap_or_else(|| resolved.name.clone());
+            if pack.get_spec(&name).is_some() {
+                error(&format!("There is already a mod called {} in the pack, use --name to choose another name!", name));
+                return Ok(());
+            }
+            let output_path = spec.output_path.clone().unwrap_or_else(|| format!("./mods/{}", resolved.file_name));
+            if let Some(existing) = pack.get_mods().iter().find(|m| normalize_path(&m.output_path) == normalize_path(&output_path)) {
+                error(&format!("{} is already saved to {}, use --output-path to choose another path!", existing.name, output_path));
+                return Ok(());
+            }
+            let mut mod_metadata = source.hash(resolved, response).await?.into_metadata().await?;
+            mod_metadata.name = name.clone();
+            if let Some(output_path) = &spec.output_path {
+                mod_metadata.output_path = output_path.clone();
+            }
+            pack.add(spec, mod_metadata);
+            pack.save(pack_path)?;
+            complete(&format!("Added {} to the pack!", name));
+        }
+
+        Opt::MavenAdd { repository, coordinate, .. } => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let mut pack = ModPack::load(pack_path)?;
+
+            let spec = ModSpec {
+                repository: Some(repository),
+                classifier: coordinate.classifier,
+                ..ModSpec::new(SourceKind::Maven, format!("{}:{}", coordinate.group, coordinate.artifact), coordinate.version)
+            };
+            info(&format!("Resolving {}...", spec.id));
+
+            let resolver = Resolver::new(None)?;
+            match resolver.resolve(&pack, &coordinate.artifact, &spec).await? {
+                None => warning(&format!("No matching version of {} found!", spec.id)),
+                Some(mod_metadata) => {
+                    let version = mod_metadata.update_info.as_ref().map(|update_info| update_info.version());
+                    pack.add(spec, mod_metadata);
+                    pack.save(pack_path)?;
+                    complete(&format!("Added {} {} to the pack!", coordinate.artifact, version.unwrap_or_default()));
+                }
+            }
+        }
+
+        Opt::Update { mods } => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let mut pack = ModPack::load(pack_path)?;
+            let targets = select_mods(&pack, mods);
+            let updater = updater_for(&targets)?;
+
+            let mut count = 0;
+            for mod_metadata in targets {
+                let update_info = match &mod_metadata.update_info {
+                    None => {
+                        warning(&format!("{} is unmanaged (no update_info), skipping!", mod_metadata.name));
+                        continue;
+                    }
+                    Some(update_info) => update_info,
+                };
+
+                let spec = pack.get_spec(&mod_metadata.name).cloned();
+                if let Some(version) = spec.as_ref().and_then(|s| s.pinned_version()) {
+                    info(&format!("{} is pinned to {} in pack.toml, skipping!", mod_metadata.name, version));
+                    continue;
+                }
+
+                info(&format!("Checking {} for updates...", mod_metadata.name));
+                match updater.check(&pack, &mod_metadata, update_info).await? {
+                    UpdateCheck::UpToDate => info(&format!("{} is up to date!", mod_metadata.name)),
+                    UpdateCheck::NoCompatibleVersion => warning(&format!("No compatible version found for {}!", mod_metadata.name)),
+                    UpdateCheck::Available { from, resolved } => {
+                        let to = resolved.version_name.clone();
+                        let mut metadata = apply_update(&mod_metadata, *resolved).await?;
+                        if let Some(spec) = spec {
+                            if let Some(output_path) = spec.output_path {
+                                metadata.output_path = output_path;
+                            }
+                            if let Some(side) = spec.side {
+                                metadata.side = side;
+                            }
+                            metadata.add_mirrors(spec.mirrors);
+                        }
+                        pack.lock_mod(metadata);
+                        pack.save(pack_path)?;
+                        info(&format!("Updated {}: {} -> {}", mod_metadata.name, from, to));
+                        count += 1;
+                    }
+                }
+            }
+            complete(&format!("Updated {} mods!", count));
+        }
+
+        Opt::Outdated { mods } => {
+            let pack = ModPack::load(std::path::Path::new("pack.toml"))?;
+            let targets = select_mods(&pack, mods);
+            let updater = updater_for(&targets)?;
+
+            let mut count = 0;
+            for mod_metadata in targets {
+                let update_info = match &mod_metadata.update_info {
+                    None => continue,
+                    Some(update_info) => update_info,
+                };
+
+                match updater.check(&pack, &mod_metadata, update_info).await? {
+                    UpdateCheck::UpToDate => {}
+                    UpdateCheck::NoCompatibleVersion => warning(&format!("No compatible version found for {}!", mod_metadata.name)),
+                    UpdateCheck::Available { from, resolved } => {
+                        let pinned = pack.get_spec(&mod_metadata.name).is_some_and(|spec| spec.pinned_version().is_some());
+                        info(&format!("{}: {} -> {}{}", mod_metadata.name, from, resolved.version_name,
+                                      if pinned { " (pinned in pack.toml)" } else { "" }));
+                        count += 1;
+                    }
+                }
+            }
+            if count == 0 {
+                complete("All mods are up to date!");
+            } else {
+                complete(&format!("{} mods can be updated, run `pack-it update` to update them", count));
+            }
+        }
+
+        Opt::Info { mod_name } => {
+            let pack = ModPack::load(std::path::Path::new("pack.toml"))?;
+            let spec = pack.get_spec(&mod_name);
+            let mod_metadata = pack.get_mod(&mod_name);
+            if spec.is_none() && mod_metadata.is_none() {
+                error(&format!("No mod in pack called {}!", mod_name));
+                return Ok(());
+            }
+
+            match mod_metadata.and_then(|m| m.update_info.as_ref()) {
+                Some(update_info) => {
+                    let updater = updater_for(std::slice::from_ref(mod_metadata.unwrap()))?;
+                    let description = updater.describe(update_info).await?;
+                    println!("{}", description.title.bold());
+                    print_field("Authors", description.authors.as_deref());
+                    print_field("Summary", description.summary.as_deref());
+                    print_field("Homepage", description.url.as_deref());
+                    print_field("Version", Some(&description.version));
+                }
+                None => println!("{}", mod_name.bold()),
+            }
+            if let Some(spec) = spec {
+                print_field("Pinned to", spec.version.as_deref());
+                print_field("Optional group", pack.optional_group(&mod_name));
+            }
+            match mod_metadata {
+                None => warning(&format!("{} is not locked, run `pack-it lock` first!", mod_name)),
+                Some(mod_metadata) => {
+                    print_field("File", Some(&mod_metadata.output_path));
+                    print_field("Side", Some(&format!("{:?}", mod_metadata.side).to_lowercase()));
+                    print_field("Download", Some(&mod_metadata.download_url));
+                    for mirror in &mod_metadata.mirror_urls {
+                        print_field("Mirror", Some(mirror));
+                    }
+                    if let Some((algorithm, hash)) = mod_metadata.hashes.strongest() {
+                        print_field(algorithm.name(), Some(hash));
+                    }
+                }
+            }
+        }
+
+        Opt::Lock => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let mut pack = ModPack::load(pack_path)?;
+
+            let unlocked = pack.unlocked_mods();
+            let needs_github = unlocked.iter().any(|(_, spec)| spec.source == SourceKind::GitHub);
+            let resolver = Resolver::new(if needs_github { Some(GithubClient::new(get_github_token())?) } else { None })?;
+
+            let mut count = 0;
+            for (name, spec) in unlocked {
+                info(&format!("Resolving {}...", name));
+                match resolver.resolve(&pack, &name, &spec).await? {
+                    None => warning(&format!("No compatible version found for {}!", name)),
+                    Some(mod_metadata) => {
+                        pack.lock_mod(mod_metadata);
+                        count += 1;
+                    }
+                }
+            }
+            for name in pack.prune_lock() {
+                info(&format!("Removed {} from pack.lock", name));
+            }
+            pack.save(pack_path)?;
+            complete(&format!("Locked {} mods!", count));
+        }
+
+        Opt::DownloadMods { jobs, prune, side, with, without, retries, timeout, .. } => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let mut pack = ModPack::load(pack_path)?;
+            if !ensure_locked(&pack) {
+                return Ok(());
+            }
+            let groups = choose_optional_groups(&pack, &with, &without)?;
+            let downloader = download::Downloader::with_options(DownloadOptions {
+                retries,
+                timeout: Duration::from_secs(timeout),
+            });
+            let mods = pack.get_mods().into_iter()
+                .filter(|mod_metadata| mod_metadata.side.includes(side))
+                .filter(|mod_metadata| pack.optional_group(&mod_metadata.name).is_none_or(|group| groups.contains(group)))
+                .collect();
+            let results = downloader.download_mods(mods, jobs).await;
+
+            let mut state = InstallState::read(pack_path)?;
+            let mut failed = 0;
+            let mut upgraded = 0;
+            for (mod_metadata, result) in &results {
+                match result {
+                    Ok(()) => {
+                        state.record(&mod_metadata.output_path);
+                        // mods locked by older versions only have a SHA-1 hash, so add stronger ones now the file is verified
+                        if mod_metadata.hashes.get(HashAlgorithm::Sha512).is_none() {
+                            let mut upgraded_metadata = mod_metadata.clone();
+                            upgraded_metadata.hashes.merge(hash_file(Path::new(&mod_metadata.output_path), &[HashAlgorithm::Sha1, HashAlgorithm::Sha512])?);
+                            pack.lock_mod(upgraded_metadata);
+                            upgraded += 1;
+                        }
+                    }
+                    Err(e) => {
+                        error(&format!("Failed to download {}: {}", mod_metadata.name, e));
+                        failed += 1;
+                    }
+                }
+            }
+            if prune {
+                prune::prune(&pack, &mut state, false)?;
+            }
+            state.save(pack_path)?;
+            if upgraded > 0 {
+                pack.save(pack_path)?;
+                info(&format!("Added SHA-512 hashes for {} mods to pack.lock", upgraded));
+            }
+
+            if failed > 0 {
+                anyhow::bail!("{} of {} mods failed to download", failed, results.len());
+            }
+            complete(&format!("All {} mods are downloaded!", results.len()));
+        }
+
+        Opt::Cache(CacheCommand::Gc { max_age, unused }) => {
+            let cache = Cache::open()?;
+            let result = cache.gc(Duration::from_secs(max_age * 24 * 60 * 60), unused)?;
+            complete(&format!("Removed {} files from the cache, freeing {:.1} MB!", result.removed, result.freed_bytes as f64 / 1_000_000.0));
+        }
+
+        Opt::Prune { all } => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let pack = ModPack::load(pack_path)?;
+            if !ensure_locked(&pack) {
+                return Ok(());
+            }
+            let mut state = InstallState::read(pack_path)?;
+            let removed = prune::prune(&pack, &mut state, all)?;
+            state.save(pack_path)?;
+            complete(&format!("Removed {} files!", removed.len()));
+        }
+
+        Opt::Export(format) => {
+            let pack_path = std::path::Path::new("pack.toml");
+            let pack = ModPack::load(pack_path)?;
+            if !ensure_locked(&pack) {
+                return Ok(());
+            }
+
+            match format {
+                ExportFormat::Mrpack { output, bundle_other } => {
+                    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.mrpack", pack.pack_name())));
+                    formats::mrpack::export(&pack, &output, bundle_other).await?;
+                    complete(&format!("Exported the pack to {}!", output.display()));
+                }
+                ExportFormat::Curseforge { output, bundle_other } => {
+                    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.zip", pack.pack_name())));
+                    formats::curseforge::export(&pack, &output, bundle_other).await?;
+                    complete(&format!("Exported the pack to {}!", output.display()));
+                }
+                ExportFormat::Packwiz { directory } => {
+                    // packwiz also uses a pack.toml, so exporting into this directory would overwrite ours
+                    if directory.canonicalize().ok() == std::env::current_dir().ok() {
+                        error("Can't export a packwiz pack into the same directory as pack.toml!");
+                        return Ok(());
+                    }
+                    formats::packwiz::export(&pack, &directory)?;
+                    complete(&format!("Exported the pack to {}!", directory.display()));
+                }
+                ExportFormat::Prism { output } => {
+                    formats::prism::export(&pack, &output).await?;
+                    complete(&format!("Exported the pack to {}!", output.display()));
+                }
+            }
+        }
+
+        Opt::Import(format) => {
             let pack_path = std::path::Path::new("pack.toml");
-            let pack = ModPack::read(&mut std::fs::File::open(pack_path)?)?;
-            let downloader = download::Downloader::new();
-            for mod_metadata in pack.get_mods() {
-                info(&*format!("Processing {}...", mod_metadata.name));
-                let path = std::path::Path::new(&mod_metadata.output_path);
-                downloader.download_if_hash_invalid(path, &mod_metadata.download_url, &mod_metadata.download_hash).await?;
+            if pack_path.exists() {
+                error("A pack.toml file already exists in this directory!");
+                return Ok(());
             }
+
+            let pack = match format {
+                ImportFormat::Mrpack { file } => formats::mrpack::import(&file, Path::new(".")).await?,
+                ImportFormat::Curseforge { file } => formats::curseforge::import(&file, Path::new(".")).await?,
+                ImportFormat::Packwiz { directory } => formats::packwiz::import(&directory, Path::new(".")).await?,
+            };
+            pack.save(pack_path)?;
+            complete(&format!("Imported {} mods into pack.toml!", pack.get_mods().len()));
         }
 
         Opt::Remove { mods } => {
             let pack_path = std::path::Path::new("pack.toml");
-            let mut pack = ModPack::read(&mut std::fs::File::open(pack_path)?)?;
+            let mut pack = ModPack::load(pack_path)?;
             let mut count = 0;
             for mod_name in mods {
                 if !pack.remove(&mod_name) {
-                    error(&*format!("No mod in pack called {}!", mod_name));
+                    error(&format!("No mod in pack called {}!", mod_name));
                 } else {
-                    info(&*format!("Removed {} from the pack!", mod_name));
+                    info(&format!("Removed {} from the pack!", mod_name));
                     count += 1;
                 }
             }
-            pack.save(&pack_path)?;
-            complete(&*format!("Removed {} mods from the pack!", count));
+            pack.save(pack_path)?;
+            complete(&format!("Removed {} mods from the pack!", count));
         }
     }
 
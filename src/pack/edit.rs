@@ -0,0 +1,247 @@
+use toml_edit::{Decor, DocumentMut, Item, Table, TableLike, Value};
+
+/// Applies `updated` onto `original`, only touching the keys whose values actually changed, so that
+/// comments, key order and blank lines elsewhere in the file are kept.
+pub fn merge_document(original: &mut DocumentMut, updated: &DocumentMut) {
+    merge_table(original.as_table_mut(), updated.as_table());
+}
+
+/// Gives each `[from.<name>]` table's comments and place in the file to a new `[to.<name>]` table, so that they
+/// survive moving the section when the rest of it is merged in.
+pub fn carry_table_decor(document: &mut DocumentMut, from: &str, to: &str) {
+    let tables: Vec<(String, Decor, Option<usize>)> = match document.get(from).and_then(Item::as_table) {
+        None => return,
+        Some(table) => table.iter()
+            .filter_map(|(name, item)| item.as_table().map(|table| (name.to_string(), table.decor().clone(), table.position())))
+            .collect(),
+    };
+    let parent = document.entry(to).or_insert_with(|| {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    let parent = match parent.as_table_mut() {
+        None => return,
+        Some(parent) => parent,
+    };
+    for (name, decor, position) in tables {
+        if parent.contains_key(&name) {
+            continue;
+        }
+        let mut table = Table::new();
+        *table.decor_mut() = decor;
+        if let Some(position) = position {
+            table.set_position(position);
+        }
+        parent.insert(&name, Item::Table(table));
+    }
+}
+
+fn merge_table(original: &mut dyn TableLike, updated: &dyn TableLike) {
+    let removed: Vec<String> = original.iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !updated.contains_key(key))
+        .collect();
+    for key in removed {
+        original.remove(&key);
+    }
+
+    for (key, item) in updated.iter() {
+        match original.get_mut(key) {
+            Some(existing) => merge_item(existing, item),
+            None => {
+                original.insert(key, expand(item.clone()));
+            }
+        }
+    }
+}
+
+fn merge_item(original: &mut Item, updated: &Item) {
+    if let (Some(table), Some(updated)) = (original.as_inline_table_mut(), updated.as_table_like()) {
+        let len = table.len();
+        merge_table(table, updated);
+        // the space before the closing brace belongs to the last value, so new keys would end up after it
+        if table.len() > len {
+            table.fmt();
+        }
+        return;
+    }
+    if original.is_table_like() && updated.is_table_like() {
+        merge_table(original.as_table_like_mut().expect("is_table_like returned true"),
+                    updated.as_table_like().expect("is_table_like returned true"));
+        return;
+    }
+
+    match (original.as_value_mut(), updated.as_value()) {
+        (Some(existing), Some(value)) => if !same_value(existing, value) {
+            // keep any comment attached to the old value
+            let decor = existing.decor().clone();
+            *existing = value.clone();
+            *existing.decor_mut() = decor;
+        },
+        _ => *original = expand(updated.clone()),
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len()
+            && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b)),
+        (Value::InlineTable(a), Value::InlineTable(b)) => a.len() == b.len()
+            && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| same_value(a, b))),
+        _ => false,
+    }
+}
+
+/// Turns serialized inline tables into standard `[table]` sections, which is how pack.toml is laid out.
+fn expand(item: Item) -> Item {
+    match item {
+        Item::Value(Value::InlineTable(table)) => {
+            let mut table = table.into_table();
+            for (_, value) in table.iter_mut() {
+                *value = expand(std::mem::take(value));
+            }
+            Item::Table(table)
+        }
+        item => item,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACK: &str = r#"# My pack
+pack_name = "Test"
+mod_loader = "fabric" # only fabric for now
+
+[mods]
+# the essentials
+sodium = { source = "mr", id = "sodium" }
+
+[mods.lithium]
+source = "mr"
+id = "lithium"
+version = "abc" # pinned until the next release
+"#;
+
+    /// Merges the document pack-it would serialize, given here as plain TOML, into `original`.
+    fn merge(original: &str, updated: &str) -> String {
+        let mut document: DocumentMut = original.parse().unwrap();
+        let updated: toml::Value = updated.parse().unwrap();
+        merge_document(&mut document, &toml_edit::ser::to_document(&updated).unwrap());
+        document.to_string()
+    }
+
+    #[test]
+    fn unchanged_document_is_kept_as_is() {
+        let updated = r#"
+            pack_name = "Test"
+            mod_loader = "fabric"
+            [mods.sodium]
+            source = "mr"
+            id = "sodium"
+            [mods.lithium]
+            source = "mr"
+            id = "lithium"
+            version = "abc"
+        "#;
+        assert_eq!(merge(PACK, updated), PACK);
+    }
+
+    #[test]
+    fn added_mod_is_appended_as_a_table() {
+        let updated = r#"
+            pack_name = "Test"
+            mod_loader = "fabric"
+            [mods.sodium]
+            source = "mr"
+            id = "sodium"
+            [mods.lithium]
+            source = "mr"
+            id = "lithium"
+            version = "abc"
+            [mods.jei]
+            source = "cf"
+            id = "jei"
+        "#;
+        // toml::Value keeps its keys sorted, unlike the structs pack-it really serializes
+        let expected = format!("{}\n[mods.jei]\nid = \"jei\"\nsource = \"cf\"\n", PACK);
+        assert_eq!(merge(PACK, updated), expected);
+    }
+
+    #[test]
+    fn removed_mod_leaves_the_rest_alone() {
+        let updated = r#"
+            pack_name = "Test"
+            mod_loader = "fabric"
+            [mods.lithium]
+            source = "mr"
+            id = "lithium"
+            version = "abc"
+        "#;
+        let expected = PACK.replace("# the essentials\nsodium = { source = \"mr\", id = \"sodium\" }\n", "");
+        assert_eq!(merge(PACK, updated), expected);
+    }
+
+    #[test]
+    fn updated_value_keeps_its_comment() {
+        let updated = r#"
+            pack_name = "Test"
+            mod_loader = "fabric"
+            [mods.sodium]
+            source = "mr"
+            id = "sodium"
+            [mods.lithium]
+            source = "mr"
+            id = "lithium"
+            version = "def"
+        "#;
+        assert_eq!(merge(PACK, updated), PACK.replace("\"abc\"", "\"def\""));
+    }
+
+    #[test]
+    fn inline_table_mod_is_merged_in_place() {
+        let updated = r#"
+            pack_name = "Test"
+            mod_loader = "fabric"
+            [mods.sodium]
+            source = "mr"
+            id = "sodium"
+            version = "xyz"
+            [mods.lithium]
+            source = "mr"
+            id = "lithium"
+            version = "abc"
+        "#;
+        let expected = PACK.replace("id = \"sodium\" }", "id = \"sodium\", version = \"xyz\" }");
+        assert_eq!(merge(PACK, updated), expected);
+    }
+
+    #[test]
+    fn moved_tables_keep_their_comments_and_place() {
+        let legacy = r#"pack_name = "Test"
+
+# pinned on purpose
+[installed_mods.foo]
+name = "foo"
+
+[installed_mods.foo.update_info]
+type = "gh"
+"#;
+        let mut document: DocumentMut = legacy.parse().unwrap();
+        carry_table_decor(&mut document, "installed_mods", "mods");
+        let updated: toml::Value = r#"
+            pack_name = "Test"
+            [mods.foo]
+            id = "o/foo"
+        "#.parse().unwrap();
+        merge_document(&mut document, &toml_edit::ser::to_document(&updated).unwrap());
+        assert_eq!(document.to_string(), "pack_name = \"Test\"\n\n# pinned on purpose\n[mods.foo]\nid = \"o/foo\"\n");
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use crate::pack::ModMetadata;
+
+const LOCK_HEADER: &str = "# This file is automatically generated by pack-it.\n# It is not intended for manual editing.\n";
+
+/// The resolved artifacts for the mods in a pack, stored in `pack.lock` next to `pack.toml`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LockFile {
+    #[serde(default)]
+    pub mods: BTreeMap<String, ModMetadata>,
+}
+
+impl LockFile {
+    pub fn read(lock_file: &Path) -> Result<Self> {
+        if !lock_file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(lock_file)?)?)
+    }
+
+    pub fn save(&self, lock_file: &Path) -> Result<()> {
+        fs::write(lock_file, format!("{}\n{}", LOCK_HEADER, toml::to_string(&self)?))?;
+        Ok(())
+    }
+}
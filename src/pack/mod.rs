@@ -1,13 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use serde::{Serialize, Deserialize};
-use std::fs::File;
 use std::fs;
 use anyhow::Result;
-use std::path::Path;
-use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::fmt::Debug;
+use std::str::FromStr;
 use crate::sources::curseforge::CurseforgeModFile;
 use crate::sources::modrinth::ModrinthVersion;
+use crate::sources::maven;
+use crate::sources::gitea::GITEA_HOST;
+use crate::sources::gitlab::GITLAB_HOST;
+use crate::pack::lock::LockFile;
+use crate::hash::FileHashes;
+use crate::util::info;
+use toml_edit::DocumentMut;
+
+pub mod lock;
+mod edit;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ModPack {
@@ -15,24 +24,64 @@ pub struct ModPack {
     author: Option<String>,
     accepted_game_versions: Vec<String>,
     mod_loader: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    loader_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pack_version: Option<String>,
+
+    #[serde(default)]
+    mods: BTreeMap<String, ModSpec>,
 
+    // Packs created before pack.lock existed stored the resolved mods here; they are migrated on load.
+    #[serde(default, skip_serializing)]
     installed_mods: HashMap<String, ModMetadata>,
+
+    #[serde(skip)]
+    lock: LockFile,
+
+    // The pack.toml as it was read, so that saving only touches what changed
+    #[serde(skip)]
+    document: Option<DocumentMut>,
 }
 
 impl ModPack {
-    pub fn read(file: &mut File) -> Result<Self> {
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        Ok(toml::from_str(&*content)?)
+    pub fn load(pack_file: &Path) -> Result<Self> {
+        let content = fs::read_to_string(pack_file)?;
+        let mut pack: Self = toml::from_str(&content)?;
+        pack.document = Some(content.parse()?);
+        pack.lock = LockFile::read(&lock_file_path(pack_file))?;
+
+        if !pack.installed_mods.is_empty() {
+            info("Migrating installed_mods from pack.toml to pack.lock...");
+            if let Some(document) = &mut pack.document {
+                edit::carry_table_decor(document, "installed_mods", "mods");
+            }
+            for (name, mod_metadata) in pack.installed_mods.drain() {
+                if !pack.mods.contains_key(&name) {
+                    let spec = ModSpec::from_legacy(&mod_metadata);
+                    if let Some(version) = &spec.version {
+                        info(&format!("{} is pinned to {}, remove its version from pack.toml to let `pack-it update` update it", name, version));
+                    }
+                    pack.mods.insert(name.clone(), spec);
+                }
+                pack.lock.mods.entry(name).or_insert(mod_metadata);
+            }
+        }
+        Ok(pack)
     }
 
-    pub fn new(name: String, author: String, game_versions: Vec<String>, mod_loader: String) -> Self {
+    pub fn new(name: String, author: Option<String>, game_versions: Vec<String>, mod_loader: String) -> Self {
         Self {
             pack_name: Some(name),
-            author: Some(author),
+            author,
+            mods: BTreeMap::new(),
             installed_mods: HashMap::new(),
             accepted_game_versions: game_versions,
             mod_loader,
+            loader_version: None,
+            pack_version: None,
+            lock: LockFile::default(),
+            document: None,
         }
     }
 
@@ -65,40 +114,311 @@ impl ModPack {
     //     }
     // }
 
-    pub fn add(&mut self, mod_info: ModMetadata) {
-        self.installed_mods.insert(mod_info.name.clone(), mod_info);
+    pub fn pack_name(&self) -> &str {
+        self.pack_name.as_deref().unwrap_or("pack")
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn pack_version(&self) -> &str {
+        self.pack_version.as_deref().unwrap_or("1.0.0")
+    }
+
+    pub fn mod_loader(&self) -> &str {
+        &self.mod_loader
+    }
+
+    pub fn loader_version(&self) -> Option<&str> {
+        self.loader_version.as_deref()
+    }
+
+    pub fn game_versions(&self) -> &[String] {
+        &self.accepted_game_versions
+    }
+
+    pub fn set_loader_version(&mut self, loader_version: Option<String>) {
+        self.loader_version = loader_version;
+    }
+
+    pub fn set_pack_version(&mut self, pack_version: Option<String>) {
+        self.pack_version = pack_version;
+    }
+
+    /// Adds a mod to pack.toml, along with the artifact it resolved to.
+    pub fn add(&mut self, spec: ModSpec, mod_info: ModMetadata) {
+        self.mods.insert(mod_info.name.clone(), spec);
+        self.lock_mod(mod_info);
+    }
+
+    /// Records the artifact a mod resolved to in the lockfile, leaving pack.toml untouched.
+    pub fn lock_mod(&mut self, mod_info: ModMetadata) {
+        self.lock.mods.insert(mod_info.name.clone(), mod_info);
     }
 
     pub fn save(&self, pack_file: &Path) -> Result<()> {
-        fs::write(pack_file, toml::to_string(&self)?)?;
+        let mut document = self.document.clone().unwrap_or_default();
+        edit::merge_document(&mut document, &toml_edit::ser::to_document(&self)?);
+        fs::write(pack_file, document.to_string())?;
+        self.lock.save(&lock_file_path(pack_file))?;
         Ok(())
     }
 
     pub fn resolve_curseforge_version(&self, files: &[CurseforgeModFile]) -> Option<CurseforgeModFile> {
         let mut filtered = files.iter().filter(|&file| {
-            return file.game_version.iter().map(|v| v.to_lowercase()).any(|v| v.eq(&self.mod_loader))
-                && file.game_version.iter().any(|v| self.accepted_game_versions.contains(v));
+            file.game_version.iter().map(|v| v.to_lowercase()).any(|v| v.eq(&self.mod_loader))
+                && file.game_version.iter().any(|v| self.accepted_game_versions.contains(v))
         }).collect::<Vec<&CurseforgeModFile>>();
-        filtered.sort_by(|&v1, &v2| v1.file_date.cmp(&v2.file_date));
+        filtered.sort_by_key(|&v1| v1.file_date);
         // this is rust so I have no idea what I am doing, but this appears to work
         filtered.last().cloned().cloned()
     }
 
     pub fn supports(&self, version: &ModrinthVersion) -> bool {
-        return version.loaders.contains(&self.mod_loader)
+        version.loaders.contains(&self.mod_loader)
             && version.game_versions.iter().any(|v| self.accepted_game_versions.contains(v))
     }
 
+    /// Returns the locked mods in the pack.
     pub fn get_mods(&self) -> Vec<ModMetadata> {
         let mut mods: Vec<ModMetadata> = vec![];
-        for m in self.installed_mods.values() {
+        for m in self.lock.mods.values() {
             mods.push(m.clone());
         }
         mods
     }
 
+    pub fn get_mod(&self, mod_name: &str) -> Option<&ModMetadata> {
+        self.lock.mods.get(mod_name)
+    }
+
+    pub fn get_spec(&self, mod_name: &str) -> Option<&ModSpec> {
+        self.mods.get(mod_name)
+    }
+
+    /// The group an optional mod belongs to, or `None` if the mod is always installed.
+    pub fn optional_group(&self, mod_name: &str) -> Option<&str> {
+        self.mods.get_key_value(mod_name).and_then(|(name, spec)| spec.optional_group(name))
+    }
+
+    /// Every group of optional mods in the pack.
+    pub fn optional_groups(&self) -> BTreeSet<&str> {
+        self.mods.iter()
+            .filter_map(|(name, spec)| spec.optional_group(name))
+            .collect()
+    }
+
+    /// Returns the mods in pack.toml that have no entry in pack.lock, or whose entry no longer matches.
+    pub fn unlocked_mods(&self) -> Vec<(String, ModSpec)> {
+        self.mods.iter()
+            .filter(|(name, spec)| !self.lock.mods.get(*name).is_some_and(|m| spec.is_satisfied_by(m)))
+            .map(|(name, spec)| (name.clone(), spec.clone()))
+            .collect()
+    }
+
+    /// Drops entries from pack.lock that are no longer in pack.toml, returning their names.
+    pub fn prune_lock(&mut self) -> Vec<String> {
+        let stale: Vec<String> = self.lock.mods.keys()
+            .filter(|name| !self.mods.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in &stale {
+            self.lock.mods.remove(name);
+        }
+        stale
+    }
+
+    pub fn has_curseforge_addon(&self, addon_id: i32) -> bool {
+        self.lock.mods.values().any(|m| matches!(&m.update_info,
+            Some(ModUpdateMetadata::Curseforge { addon_id: id, .. }) if *id == addon_id))
+    }
+
+    pub fn has_modrinth_project(&self, project_id: &str) -> bool {
+        self.lock.mods.values().any(|m| matches!(&m.update_info,
+            Some(ModUpdateMetadata::Modrinth { project_id: id, .. }) if id == project_id))
+    }
+
     pub fn remove(&mut self, mod_name: &str) -> bool {
-        self.installed_mods.remove(mod_name).is_some()
+        let locked = self.lock.mods.remove(mod_name).is_some();
+        self.mods.remove(mod_name).is_some() || locked
+    }
+}
+
+fn lock_file_path(pack_file: &Path) -> PathBuf {
+    pack_file.with_file_name("pack.lock")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SourceKind {
+    #[serde(rename = "cf")]
+    Curseforge,
+    #[serde(rename = "mr")]
+    Modrinth,
+    #[serde(rename = "gh")]
+    GitHub,
+    #[serde(rename = "url")]
+    Url,
+    #[serde(rename = "maven")]
+    Maven,
+    #[serde(rename = "gl")]
+    GitLab,
+    #[serde(rename = "gitea")]
+    Gitea,
+}
+
+/// What was asked for when a mod was added to the pack; `pack-it lock` resolves this to a [`ModMetadata`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModSpec {
+    pub source: SourceKind,
+    /// The slug or ID of the mod, `owner/repo` for GitHub and Gitea, the path of the project for GitLab,
+    /// `group:artifact` for Maven, or the download URL for `url`.
+    pub id: String,
+    /// Pins the mod to a file ID, version ID, tag or Maven version. The latest compatible version is used otherwise.
+    /// For Maven this can also be a range, such as `[1.0,2.0)`, which the mod is kept within.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The URL of the Maven repository the artifact is published to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// The classifier of the Maven artifact's file, eg. `api` for `<artifact>-<version>-api.jar`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classifier: Option<String>,
+    /// The base URL of the GitLab or Gitea instance the project is hosted on, if it isn't gitlab.com or codeberg.org.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub staging: Option<bool>,
+    /// Overrides where the file is saved, instead of `./mods/<file name>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_path: Option<String>,
+    /// Overrides which side the mod is installed on, instead of what its source reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<ModSide>,
+    /// Only installs the mod when it is chosen.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// Puts the mod into a named group of optional mods that are chosen together. Implies `optional`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Extra URLs to try, after any that the source knows about, if the download fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+}
+
+impl ModSpec {
+    pub fn new(source: SourceKind, id: String, version: Option<String>) -> Self {
+        Self {
+            source,
+            id,
+            version,
+            repository: None,
+            classifier: None,
+            host: None,
+            staging: None,
+            output_path: None,
+            side: None,
+            optional: false,
+            group: None,
+            mirrors: vec![],
+        }
+    }
+
+    /// The version the mod is pinned to, if any. Maven version ranges don't count, as the mod can still be updated
+    /// within them.
+    pub fn pinned_version(&self) -> Option<&String> {
+        self.version.as_ref()
+            .filter(|version| !(self.source == SourceKind::Maven && maven::is_range(version)))
+    }
+
+    /// Optional mods without a group are chosen on their own, so they are a group named after the mod.
+    fn optional_group<'a>(&'a self, mod_name: &'a str) -> Option<&'a str> {
+        match &self.group {
+            Some(group) => Some(group),
+            None if self.optional => Some(mod_name),
+            None => None,
+        }
+    }
+
+    fn from_legacy(mod_info: &ModMetadata) -> Self {
+        match &mod_info.update_info {
+            Some(ModUpdateMetadata::Curseforge { .. }) => Self::new(SourceKind::Curseforge, mod_info.name.clone(), None),
+            Some(ModUpdateMetadata::Modrinth { staging, .. }) => Self {
+                staging: *staging,
+                ..Self::new(SourceKind::Modrinth, mod_info.name.clone(), None)
+            },
+            // github-add always asked for a tag, so these mods were pinned to it
+            Some(ModUpdateMetadata::GitHub { owner, repo, tag }) => Self::new(SourceKind::GitHub, format!("{}/{}", owner, repo), Some(tag.clone())),
+            Some(ModUpdateMetadata::GitLab { host, project, .. }) => Self {
+                host: Some(host.clone()).filter(|host| host != GITLAB_HOST),
+                ..Self::new(SourceKind::GitLab, project.clone(), None)
+            },
+            Some(ModUpdateMetadata::Gitea { host, owner, repo, .. }) => Self {
+                host: Some(host.clone()).filter(|host| host != GITEA_HOST),
+                ..Self::new(SourceKind::Gitea, format!("{}/{}", owner, repo), None)
+            },
+            Some(ModUpdateMetadata::Maven { repository, group, artifact, classifier, range, .. }) => Self {
+                repository: Some(repository.clone()),
+                classifier: classifier.clone(),
+                ..Self::new(SourceKind::Maven, format!("{}:{}", group, artifact), range.clone())
+            },
+            Some(ModUpdateMetadata::Url { .. }) | None => Self {
+                output_path: Some(mod_info.output_path.clone()),
+                ..Self::new(SourceKind::Url, mod_info.download_url.clone(), None)
+            },
+        }
+    }
+
+    /// Checks whether a locked mod still matches what this spec asks for.
+    pub fn is_satisfied_by(&self, mod_info: &ModMetadata) -> bool {
+        if self.output_path.as_ref().is_some_and(|p| *p != mod_info.output_path) {
+            return false;
+        }
+        if self.side.is_some_and(|side| side != mod_info.side) {
+            return false;
+        }
+        if !self.mirrors.iter().all(|mirror| mod_info.mirror_urls.contains(mirror)) {
+            return false;
+        }
+
+        match (&self.source, &mod_info.update_info) {
+            (SourceKind::Curseforge, Some(ModUpdateMetadata::Curseforge { addon_id, file_id })) => {
+                (self.id == mod_info.name || self.id == addon_id.to_string())
+                    && self.version.as_ref().is_none_or(|v| *v == file_id.to_string())
+            }
+            (SourceKind::Modrinth, Some(ModUpdateMetadata::Modrinth { project_id, version_id, staging })) => {
+                (self.id == mod_info.name || self.id == *project_id)
+                    && self.version.as_ref().is_none_or(|v| v == version_id)
+                    && self.staging.unwrap_or(false) == staging.unwrap_or(false)
+            }
+            (SourceKind::GitHub, Some(ModUpdateMetadata::GitHub { owner, repo, tag })) => {
+                self.id == format!("{}/{}", owner, repo)
+                    && self.version.as_ref().is_none_or(|v| v == tag)
+            }
+            (SourceKind::GitLab, Some(ModUpdateMetadata::GitLab { host, project, tag })) => {
+                self.id == *project
+                    && self.host.as_deref().unwrap_or(GITLAB_HOST) == host
+                    && self.version.as_ref().is_none_or(|v| v == tag)
+            }
+            (SourceKind::Gitea, Some(ModUpdateMetadata::Gitea { host, owner, repo, tag })) => {
+                self.id == format!("{}/{}", owner, repo)
+                    && self.host.as_deref().unwrap_or(GITEA_HOST) == host
+                    && self.version.as_ref().is_none_or(|v| v == tag)
+            }
+            (SourceKind::Url, Some(ModUpdateMetadata::Url { url, .. })) => self.id == *url,
+            (SourceKind::Maven, Some(ModUpdateMetadata::Maven { repository, group, artifact, version, classifier, range })) => {
+                self.id == format!("{}:{}", group, artifact)
+                    && self.repository.as_ref() == Some(repository)
+                    && self.classifier == *classifier
+                    && match &self.version {
+                        Some(v) if maven::is_range(v) => range.as_ref() == Some(v),
+                        Some(v) => v == version && range.is_none(),
+                        None => range.is_none(),
+                    }
+            }
+            (SourceKind::Url, None) => self.id == mod_info.download_url,
+            _ => false,
+        }
     }
 }
 
@@ -106,13 +426,79 @@ impl ModPack {
 pub struct ModMetadata {
     pub name: String,
     pub download_url: String,
-    pub download_hash: String,
+    /// Other places the same file can be downloaded from, tried in order if `download_url` fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirror_urls: Vec<String>,
     pub output_path: String,
+    #[serde(default, skip_serializing_if = "ModSide::is_both")]
+    pub side: ModSide,
+    /// Older versions only stored a SHA-1 hash, as `download_hash`.
+    #[serde(alias = "download_hash")]
+    pub hashes: FileHashes,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub update_info: Option<ModUpdateMetadata>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl ModMetadata {
+    /// Every URL the file can be downloaded from, in the order they should be tried.
+    pub fn download_urls(&self) -> Vec<&str> {
+        std::iter::once(&self.download_url)
+            .chain(&self.mirror_urls)
+            .map(|url| url.as_str())
+            .collect()
+    }
+
+    /// Adds mirrors that are not already known, keeping the existing order.
+    pub fn add_mirrors(&mut self, mirrors: impl IntoIterator<Item = String>) {
+        for mirror in mirrors {
+            if mirror != self.download_url && !self.mirror_urls.contains(&mirror) {
+                self.mirror_urls.push(mirror);
+            }
+        }
+    }
+}
+
+/// Where a mod needs to be installed. Mods that don't say are assumed to be needed on both sides.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModSide {
+    Client,
+    Server,
+    #[default]
+    Both,
+}
+
+impl ModSide {
+    fn is_both(&self) -> bool {
+        *self == ModSide::Both
+    }
+
+    /// Checks whether a mod on this side should be installed when installing for `target`.
+    pub fn includes(&self, target: ModSide) -> bool {
+        *self == ModSide::Both || target == ModSide::Both || *self == target
+    }
+}
+
+impl FromStr for ModSide {
+    type Err = PackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(ModSide::Client),
+            "server" => Ok(ModSide::Server),
+            "both" => Ok(ModSide::Both),
+            _ => Err(PackError::InvalidSide(s.to_string())),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PackError {
+    #[error("{0} is not a valid side, expected client, server or both")]
+    InvalidSide(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum ModUpdateMetadata {
     #[serde(rename = "cf")]
@@ -132,5 +518,65 @@ pub enum ModUpdateMetadata {
         owner: String,
         repo: String,
         tag: String,
+    },
+    #[serde(rename = "gl")]
+    GitLab {
+        host: String,
+        project: String,
+        tag: String,
+    },
+    #[serde(rename = "gitea")]
+    Gitea {
+        host: String,
+        owner: String,
+        repo: String,
+        tag: String,
+    },
+    #[serde(rename = "url")]
+    Url {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        etag: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_modified: Option<String>,
+    },
+    #[serde(rename = "maven")]
+    Maven {
+        repository: String,
+        group: String,
+        artifact: String,
+        version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        classifier: Option<String>,
+        /// The range from pack.toml that updates have to stay within.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<String>,
+    },
+}
+
+impl ModUpdateMetadata {
+    pub fn source_kind(&self) -> SourceKind {
+        match self {
+            ModUpdateMetadata::Curseforge { .. } => SourceKind::Curseforge,
+            ModUpdateMetadata::Modrinth { .. } => SourceKind::Modrinth,
+            ModUpdateMetadata::GitHub { .. } => SourceKind::GitHub,
+            ModUpdateMetadata::GitLab { .. } => SourceKind::GitLab,
+            ModUpdateMetadata::Gitea { .. } => SourceKind::Gitea,
+            ModUpdateMetadata::Url { .. } => SourceKind::Url,
+            ModUpdateMetadata::Maven { .. } => SourceKind::Maven,
+        }
+    }
+
+    /// The file ID, version ID, tag, modification date or Maven version that this points at, for when its name can't be looked up.
+    pub fn version(&self) -> String {
+        match self {
+            ModUpdateMetadata::Curseforge { file_id, .. } => file_id.to_string(),
+            ModUpdateMetadata::Modrinth { version_id, .. } => version_id.clone(),
+            ModUpdateMetadata::GitHub { tag, .. } | ModUpdateMetadata::GitLab { tag, .. }
+            | ModUpdateMetadata::Gitea { tag, .. } => tag.clone(),
+            ModUpdateMetadata::Url { etag, last_modified, .. } => last_modified.clone().or_else(|| etag.clone())
+                .unwrap_or_else(|| "unversioned".to_string()),
+            ModUpdateMetadata::Maven { version, .. } => version.clone(),
+        }
     }
 }
@@ -0,0 +1,57 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use crate::pack::ModPack;
+use crate::state::{InstallState, normalize_path};
+use crate::util::{info, warning};
+
+/// Deletes files that pack-it installed but that are no longer referenced by any mod in the pack.
+/// With `all`, any other file in the directories that mods are installed to is deleted as well.
+/// Returns the paths that were deleted.
+pub fn prune(pack: &ModPack, state: &mut InstallState, all: bool) -> Result<Vec<String>> {
+    let referenced: BTreeSet<String> = pack.get_mods().iter()
+        .map(|m| normalize_path(&m.output_path))
+        .collect();
+
+    let mut candidates: BTreeSet<String> = state.installed_files().into_iter()
+        .filter(|path| !referenced.contains(path))
+        .collect();
+
+    if all {
+        let mut directories: BTreeSet<String> = referenced.iter().chain(state.installed_files().iter())
+            .filter_map(|path| Path::new(path).parent().map(|p| p.to_string_lossy().to_string()))
+            .collect();
+        directories.insert("mods".to_string());
+        for directory in directories {
+            // never sweep the pack directory itself, that is where pack.toml lives
+            if directory.is_empty() || !Path::new(&directory).is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&directory)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_file() {
+                    continue;
+                }
+                let path = normalize_path(&entry.path().to_string_lossy());
+                if !referenced.contains(&path) {
+                    candidates.insert(path);
+                }
+            }
+        }
+    }
+
+    let mut removed = vec![];
+    for path in candidates {
+        if Path::new(&path).exists() {
+            if !state.is_managed(&path) {
+                warning(&format!("Removing {}, which was not installed by pack-it", path));
+            }
+            fs::remove_file(&path)?;
+            info(&format!("Removed {}", path));
+            removed.push(path.clone());
+        }
+        state.forget(&path);
+    }
+    Ok(removed)
+}
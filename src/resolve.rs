@@ -0,0 +1,35 @@
+use anyhow::Result;
+use crate::pack::{ModPack, ModMetadata, ModSpec};
+use crate::sources::Sources;
+use crate::sources::github::GithubClient;
+
+/// Turns the mods requested in pack.toml into the exact files recorded in pack.lock.
+pub struct Resolver {
+    sources: Sources,
+}
+
+impl Resolver {
+    pub fn new(github_client: Option<GithubClient>) -> Result<Self> {
+        Ok(Self {
+            sources: Sources::new(github_client)?,
+        })
+    }
+
+    pub async fn resolve(&self, pack: &ModPack, name: &str, spec: &ModSpec) -> Result<Option<ModMetadata>> {
+        let mut resolved = match self.sources.for_kind(&spec.source).resolve(pack, spec).await? {
+            None => return Ok(None),
+            Some(resolved) => resolved.into_metadata().await?,
+        };
+
+        resolved.name = name.to_string();
+        if let Some(output_path) = &spec.output_path {
+            resolved.output_path = output_path.clone();
+        }
+        if let Some(side) = spec.side {
+            resolved.side = side;
+        }
+        resolved.add_mirrors(spec.mirrors.iter().cloned());
+        Ok(Some(resolved))
+    }
+}
+
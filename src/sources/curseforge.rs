@@ -1,12 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::sources::ResolutionError;
-use crate::util::USER_AGENT;
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{ModDescription, ModSource, ResolutionError, ResolvedMod, SpecError};
+use crate::util::{ensure_online, USER_AGENT};
 
 const SLUG_QUERY: &str = "query get_by_slug($slug: String) {
   addons(slug: $slug) {
@@ -24,6 +26,34 @@ const SLUG_QUERY: &str = "query get_by_slug($slug: String) {
       id
       displayName
       fileDate
+      dependencies {
+        addonId
+        type
+      }
+    }
+  }
+}";
+
+const ID_QUERY: &str = "query get_by_id($id: Int) {
+  addons(id: $id) {
+    authors {
+      name
+    }
+    name
+    summary
+    slug
+    id
+    files {
+      downloadUrl
+      fileName
+      gameVersion
+      id
+      displayName
+      fileDate
+      dependencies {
+        addonId
+        type
+      }
     }
   }
 }";
@@ -31,7 +61,7 @@ const SLUG_QUERY: &str = "query get_by_slug($slug: String) {
 #[derive(Serialize)]
 struct CurseforgeLookupGQLRequest {
     query: String,
-    variables: HashMap<String, String>,
+    variables: HashMap<String, serde_json::Value>,
     #[serde(rename = "operationName")]
     operation_name: String,
 }
@@ -61,6 +91,24 @@ impl CurseforgeAddon {
         self.authors.iter().map(|a| a.name.clone())
             .collect::<Vec<String>>().join(", ")
     }
+
+    pub fn resolved(&self, file: CurseforgeModFile) -> ResolvedMod {
+        ResolvedMod {
+            name: self.slug.clone(),
+            version_name: file.display_name,
+            update_info: ModUpdateMetadata::Curseforge {
+                addon_id: self.id,
+                file_id: file.id,
+            },
+            mirror_urls: mirror_urls(&file.download_url),
+            mirror_repository: None,
+            file_name: file.file_name,
+            download_url: file.download_url,
+            // the API doesn't give hashes, so the file has to be downloaded to hash it
+            hashes: None,
+            side: None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -77,19 +125,81 @@ pub struct CurseforgeModFile {
     pub id: i32,
     pub display_name: String,
     pub file_date: NaiveDateTime,
+    #[serde(default)]
+    pub dependencies: Vec<CurseforgeFileDependency>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseforgeFileDependency {
+    pub addon_id: i32,
+    #[serde(rename = "type")]
+    pub dependency_type: i32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CurseforgeDependencyType {
+    EmbeddedLibrary,
+    Optional,
+    Required,
+    Tool,
+    Incompatible,
+    Include,
+    Unknown,
+}
+
+impl CurseforgeFileDependency {
+    pub fn kind(&self) -> CurseforgeDependencyType {
+        match self.dependency_type {
+            1 => CurseforgeDependencyType::EmbeddedLibrary,
+            2 => CurseforgeDependencyType::Optional,
+            3 => CurseforgeDependencyType::Required,
+            4 => CurseforgeDependencyType::Tool,
+            5 => CurseforgeDependencyType::Incompatible,
+            6 => CurseforgeDependencyType::Include,
+            _ => CurseforgeDependencyType::Unknown,
+        }
+    }
+}
+
+/// A dependency that was found while resolving, along with the name of the mod that declared it.
+pub struct CurseforgeDependency {
+    pub addon: CurseforgeAddon,
+    pub file: Option<CurseforgeModFile>,
+    pub required_by: String,
+}
+
+#[derive(Default)]
+pub struct CurseforgeDependencies {
+    /// Required dependencies, resolved recursively.
+    pub required: Vec<CurseforgeDependency>,
+    /// Optional dependencies, which are not resolved any further.
+    pub optional: Vec<CurseforgeDependency>,
+    /// Mods declared as incompatible that are already present.
+    pub incompatible: Vec<CurseforgeDependency>,
+    /// Required dependencies that have no compatible file.
+    pub unresolved: Vec<CurseforgeDependency>,
 }
 
 impl CurseforgeLookupGQLRequest {
     fn create_slug_lookup(slug: &str) -> Self {
         Self {
             query: SLUG_QUERY.to_string(),
-            variables: make_map_pair("slug", slug.to_string()),
+            variables: make_map_pair("slug", slug.into()),
             operation_name: "get_by_slug".to_string(),
         }
     }
+
+    fn create_id_lookup(id: i32) -> Self {
+        Self {
+            query: ID_QUERY.to_string(),
+            variables: make_map_pair("id", id.into()),
+            operation_name: "get_by_id".to_string(),
+        }
+    }
 }
 
-fn make_map_pair(a: &str, b: String) -> HashMap<String, String> {
+fn make_map_pair(a: &str, b: serde_json::Value) -> HashMap<String, serde_json::Value> {
     let mut mp = HashMap::with_capacity(1);
     mp.insert(a.to_string(), b);
     mp
@@ -99,6 +209,18 @@ pub struct CurseforgeClient {
     client: Client,
 }
 
+/// CurseForge serves the same files from both of its CDN hosts, so either can stand in for the other.
+pub fn mirror_urls(download_url: &str) -> Vec<String> {
+    const CDN_HOSTS: [&str; 2] = ["https://edge.forgecdn.net/", "https://mediafilez.forgecdn.net/"];
+    CDN_HOSTS.iter()
+        .find_map(|host| download_url.strip_prefix(host))
+        .map(|path| CDN_HOSTS.iter()
+            .map(|host| format!("{}{}", host, path))
+            .filter(|url| url != download_url)
+            .collect())
+        .unwrap_or_default()
+}
+
 impl CurseforgeClient {
     pub fn new() -> Result<Self> {
         Ok(Self {
@@ -110,15 +232,134 @@ impl CurseforgeClient {
     }
 
     pub async fn find_mod_by_slug(&self, slug: &str) -> Result<CurseforgeAddon> {
-        let request = self.client.post("https://curse.nikky.moe/graphql")
-            .json(&CurseforgeLookupGQLRequest::create_slug_lookup(&slug))
-            .build()?;
-        let addons = self.client.execute(request).await?
-            .json::<CurseforgeModQuery>().await?.data.addons;
+        let addons = self.query_addons(&CurseforgeLookupGQLRequest::create_slug_lookup(slug)).await?;
         if addons.is_empty() {
             Err(ResolutionError::UnknownSlug(slug.to_string()).into())
         } else {
             Ok(addons.first().expect("is_empty returned false for an empty Vec!?").clone())
         }
     }
+
+    pub async fn find_mod_by_id(&self, id: i32) -> Result<CurseforgeAddon> {
+        let addons = self.query_addons(&CurseforgeLookupGQLRequest::create_id_lookup(id)).await?;
+        if addons.is_empty() {
+            Err(ResolutionError::UnknownId(id.to_string()).into())
+        } else {
+            Ok(addons.first().expect("is_empty returned false for an empty Vec!?").clone())
+        }
+    }
+
+    /// Walks the dependency tree of the given file. Addons for which `is_present` returns true are treated
+    /// as already satisfied and are not resolved again.
+    pub async fn resolve_dependencies(&self, addon: &CurseforgeAddon, file: &CurseforgeModFile,
+                                      resolve_version: &dyn Fn(&[CurseforgeModFile]) -> Option<CurseforgeModFile>,
+                                      is_present: &dyn Fn(i32) -> bool) -> Result<CurseforgeDependencies> {
+        let mut result = CurseforgeDependencies::default();
+        let mut seen = HashSet::new();
+        seen.insert(addon.id);
+        let mut incompatible = vec![];
+        let mut queue: VecDeque<(CurseforgeFileDependency, String)> = file.dependencies.iter()
+            .map(|d| (d.clone(), addon.name.clone()))
+            .collect();
+
+        while let Some((dependency, required_by)) = queue.pop_front() {
+            match dependency.kind() {
+                CurseforgeDependencyType::Required => {
+                    if is_present(dependency.addon_id) || !seen.insert(dependency.addon_id) {
+                        continue;
+                    }
+                    result.optional.retain(|d| d.addon.id != dependency.addon_id);
+
+                    let dependency_addon = self.find_mod_by_id(dependency.addon_id).await?;
+                    match resolve_version(&dependency_addon.files) {
+                        None => result.unresolved.push(CurseforgeDependency { addon: dependency_addon, file: None, required_by }),
+                        Some(dependency_file) => {
+                            queue.extend(dependency_file.dependencies.iter().map(|d| (d.clone(), dependency_addon.name.clone())));
+                            result.required.push(CurseforgeDependency { addon: dependency_addon, file: Some(dependency_file), required_by });
+                        }
+                    }
+                }
+                CurseforgeDependencyType::Optional => {
+                    if is_present(dependency.addon_id) || seen.contains(&dependency.addon_id)
+                        || result.optional.iter().any(|d| d.addon.id == dependency.addon_id) {
+                        continue;
+                    }
+                    let dependency_addon = self.find_mod_by_id(dependency.addon_id).await?;
+                    result.optional.push(CurseforgeDependency { addon: dependency_addon, file: None, required_by });
+                }
+                CurseforgeDependencyType::Incompatible => incompatible.push((dependency.addon_id, required_by)),
+                _ => {}
+            }
+        }
+
+        for (addon_id, required_by) in incompatible {
+            if is_present(addon_id) || seen.contains(&addon_id) {
+                let dependency_addon = self.find_mod_by_id(addon_id).await?;
+                result.incompatible.push(CurseforgeDependency { addon: dependency_addon, file: None, required_by });
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn query_addons(&self, query: &CurseforgeLookupGQLRequest) -> Result<Vec<CurseforgeAddon>> {
+        ensure_online("CurseForge")?;
+        let request = self.client.post("https://curse.nikky.moe/graphql")
+            .json(query)
+            .build()?;
+        Ok(self.client.execute(request).await?
+            .json::<CurseforgeModQuery>().await?.data.addons)
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for CurseforgeClient {
+    async fn resolve(&self, pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        let mod_data = match spec.id.parse::<i32>() {
+            Ok(addon_id) => self.find_mod_by_id(addon_id).await?,
+            Err(_) => self.find_mod_by_slug(&spec.id).await?,
+        };
+        let version = match &spec.version {
+            Some(file_id) => {
+                let file_id = file_id.parse::<i32>().map_err(|_| SpecError::InvalidFileId(file_id.clone()))?;
+                mod_data.files.iter().find(|f| f.id == file_id).cloned()
+            }
+            None => pack.resolve_curseforge_version(&mod_data.files),
+        };
+        Ok(version.map(|version| mod_data.resolved(version)))
+    }
+
+    async fn latest_compatible(&self, pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (addon_id, _) = curseforge_ids(update_info);
+        let mod_data = self.find_mod_by_id(addon_id).await?;
+        Ok(pack.resolve_curseforge_version(&mod_data.files).map(|version| mod_data.resolved(version)))
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (addon_id, file_id) = curseforge_ids(update_info);
+        let mod_data = self.find_mod_by_id(addon_id).await?;
+        let version = mod_data.files.iter().find(|f| f.id == file_id).cloned();
+        Ok(version.map(|version| mod_data.resolved(version)))
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (addon_id, file_id) = curseforge_ids(update_info);
+        let mod_data = self.find_mod_by_id(addon_id).await?;
+        Ok(ModDescription {
+            version: mod_data.files.iter().find(|f| f.id == file_id)
+                .map(|f| f.display_name.clone())
+                .unwrap_or_else(|| file_id.to_string()),
+            authors: Some(mod_data.format_authors()),
+            url: Some(format!("https://www.curseforge.com/minecraft/mc-mods/{}", mod_data.slug)),
+            title: mod_data.name,
+            summary: Some(mod_data.summary),
+        })
+    }
+}
+
+fn curseforge_ids(update_info: &ModUpdateMetadata) -> (i32, i32) {
+    match update_info {
+        ModUpdateMetadata::Curseforge { addon_id, file_id } => (*addon_id, *file_id),
+        other => unreachable!("CurseForge was asked about a mod from another source: {:?}", other),
+    }
 }
@@ -0,0 +1,197 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{api_url, ModDescription, ModSource, ResolvedMod, SpecError};
+use crate::sources::github::is_mod_jar;
+use crate::util::{ensure_online, USER_AGENT};
+
+pub const GITEA_HOST: &str = "https://codeberg.org";
+
+#[derive(Deserialize, Debug)]
+struct GiteaRelease {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<GiteaAsset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaRepository {
+    full_name: String,
+    #[serde(default)]
+    description: Option<String>,
+    html_url: String,
+    owner: GiteaUser,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaUser {
+    login: String,
+}
+
+/// Releases on a Gitea or Forgejo instance, such as Codeberg. Their API mirrors GitHub's, so releases work the
+/// same way.
+pub struct GiteaClient {
+    client: Client,
+    token: Option<String>,
+}
+
+impl GiteaClient {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(USER_AGENT)
+                .build()?,
+            token: std::env::var("GITEA_TOKEN").ok(),
+        })
+    }
+
+    /// Calls the v1 API of a repository, sending the token Gitea expects if one is set. Missing repositories and
+    /// releases become `None`.
+    async fn repo_api<T: DeserializeOwned>(&self, host: &str, owner: &str, repo: &str, path: &[&str]) -> Result<Option<T>> {
+        ensure_online("Gitea")?;
+        let url = api_url(host, &[&["api", "v1", "repos", owner, repo], path].concat())?;
+        let mut request = self.client.get(url);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("token {}", token));
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    /// Picks the mod JAR out of the assets of a tagged release, or of the latest one.
+    async fn resolve_release(&self, host: &str, owner: &str, repo: &str, tag: Option<&str>) -> Result<Option<ResolvedMod>> {
+        let path = match tag {
+            Some(tag) => vec!["releases", "tags", tag],
+            None => vec!["releases", "latest"],
+        };
+        let release: GiteaRelease = match self.repo_api(host, owner, repo, &path).await? {
+            None => return Ok(None),
+            Some(release) => release,
+        };
+
+        let asset = match release.assets.into_iter().find(|asset| is_mod_jar(&asset.name)) {
+            None => return Ok(None),
+            Some(asset) => asset,
+        };
+        Ok(Some(ResolvedMod {
+            name: repo.to_string(),
+            version_name: release.tag_name.clone(),
+            update_info: ModUpdateMetadata::Gitea {
+                host: host.to_string(),
+                owner: owner.to_string(),
+                repo: repo.to_string(),
+                tag: release.tag_name,
+            },
+            file_name: asset.name,
+            download_url: asset.browser_download_url,
+            mirror_urls: vec![],
+            mirror_repository: None,
+            hashes: None,
+            side: None,
+        }))
+    }
+}
+
+fn gitea_repository(update_info: &ModUpdateMetadata) -> (&str, &str, &str, &str) {
+    match update_info {
+        ModUpdateMetadata::Gitea { host, owner, repo, tag } => (host, owner, repo, tag),
+        other => unreachable!("Gitea was asked about a mod from another source: {:?}", other),
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for GiteaClient {
+    async fn resolve(&self, _pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        let (owner, repo) = spec.id.split_once('/')
+            .ok_or_else(|| SpecError::InvalidRepository(spec.id.clone()))?;
+        let host = spec.host.as_deref().unwrap_or(GITEA_HOST);
+        self.resolve_release(host, owner, repo, spec.version.as_deref()).await
+    }
+
+    async fn latest_compatible(&self, _pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (host, owner, repo, _) = gitea_repository(update_info);
+        self.resolve_release(host, owner, repo, None).await
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (host, owner, repo, tag) = gitea_repository(update_info);
+        self.resolve_release(host, owner, repo, Some(tag)).await
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (host, owner, repo, tag) = gitea_repository(update_info);
+        let details: GiteaRepository = self.repo_api(host, owner, repo, &[]).await?
+            .ok_or_else(|| SpecError::InvalidRepository(format!("{}/{}", owner, repo)))?;
+        Ok(ModDescription {
+            title: details.full_name,
+            authors: Some(details.owner.login),
+            summary: details.description,
+            url: Some(details.html_url),
+            version: tag.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    const RELEASE: &str = r#"{
+        "tag_name": "v1.1",
+        "assets": [
+            {"name": "mod-1.1-sources.jar", "browser_download_url": "https://example.com/mod-1.1-sources.jar"},
+            {"name": "mod-1.1.jar", "browser_download_url": "https://example.com/mod-1.1.jar"}
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn picks_the_mod_jar_from_the_latest_release() {
+        let host = test_server::serve(&[("/api/v1/repos/owner/mod/releases/latest", RELEASE)]).await;
+        let resolved = GiteaClient::new().unwrap().resolve_release(&host, "owner", "mod", None).await.unwrap().unwrap();
+        assert_eq!(resolved.name, "mod");
+        assert_eq!(resolved.file_name, "mod-1.1.jar");
+        assert_eq!(resolved.download_url, "https://example.com/mod-1.1.jar");
+        assert_eq!(resolved.update_info, ModUpdateMetadata::Gitea {
+            host: host.clone(),
+            owner: "owner".to_string(),
+            repo: "mod".to_string(),
+            tag: "v1.1".to_string(),
+        });
+    }
+
+    #[tokio::test]
+    async fn escapes_slashes_in_the_tag() {
+        let release = RELEASE.replace("v1.1", "release/1.1");
+        let host = test_server::serve(&[("/api/v1/repos/owner/mod/releases/tags/release%2F1.1", &release)]).await;
+        let resolved = GiteaClient::new().unwrap().resolve_release(&host, "owner", "mod", Some("release/1.1")).await.unwrap().unwrap();
+        assert_eq!(resolved.version_name, "release/1.1");
+        assert_eq!(resolved.file_name, "mod-1.1.jar");
+    }
+
+    #[tokio::test]
+    async fn missing_release_is_none() {
+        let host = test_server::serve(&[]).await;
+        let client = GiteaClient::new().unwrap();
+        assert!(client.resolve_release(&host, "owner", "mod", Some("v2.0")).await.unwrap().is_none());
+        assert!(client.resolve_release(&host, "owner", "mod", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn release_without_a_jar_is_none() {
+        let host = test_server::serve(&[("/api/v1/repos/owner/mod/releases/latest", r#"{"tag_name": "v1.0", "assets": []}"#)]).await;
+        assert!(GiteaClient::new().unwrap().resolve_release(&host, "owner", "mod", None).await.unwrap().is_none());
+    }
+}
@@ -1,11 +1,16 @@
 use anyhow::Result;
-use octocrab::models::repos::Asset;
-use crate::util::warning;
+use async_trait::async_trait;
+use octocrab::models::Repository;
+use octocrab::models::repos::{Asset, Release};
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{ModDescription, ModSource, ResolvedMod, SpecError};
+use crate::util::{ensure_online, warning};
 
 pub struct GithubClient { }
 
 impl GithubClient {
-    pub fn new(github_token: Option<String>) -> Self {
+    pub fn new(github_token: Option<String>) -> Result<Self> {
+        ensure_online("GitHub")?;
         if github_token.is_none() {
             warning("It is recommended to set the GITHUB_TOKEN environment variable to your Github Personal Access Token (PAT) to allow pack-it to have higher rate-limit allowances")
         }
@@ -16,7 +21,7 @@ impl GithubClient {
         }
         octocrab::initialise(octocrab_builder).expect("Failed to initialise GitHub API client!");
 
-        Self {}
+        Ok(Self {})
     }
 
     pub async fn resolve_mod(&self, owner: &str, repo: &str, tag: &str) -> Result<Option<Asset>> {
@@ -26,16 +31,123 @@ impl GithubClient {
             .get_by_tag(tag)
             .await?;
 
-        for asset in release.assets {
-            if asset.name.ends_with(".jar") && !(asset.name.contains("-dev") || asset.name.contains("-sources")) {
-                return Ok(Some(asset))
-            }
-        }
+        Ok(find_mod_asset(release))
+    }
+
+    /// Resolves the mod JAR from the latest release of a repository, along with the tag it was found under.
+    pub async fn resolve_latest(&self, owner: &str, repo: &str) -> Result<Option<(String, Asset)>> {
+        let octocrab = octocrab::instance();
+        let release = octocrab.repos(owner, repo)
+            .releases()
+            .get_latest()
+            .await?;
+
+        let tag = release.tag_name.clone();
+        Ok(find_mod_asset(release).map(|asset| (tag, asset)))
+    }
+}
+
+/// Looks for a file with the same name in the recent releases of a GitHub repository, so that it can be used as a
+/// mirror for a file from another source. Any errors are ignored, as the mirror is only nice to have, and nothing is
+/// looked up without a GITHUB_TOKEN so that the unauthenticated rate limit is left for GitHub mods.
+pub async fn find_release_asset(repository_url: &str, file_name: &str) -> Option<String> {
+    let path = repository_url.trim_end_matches('/')
+        .strip_prefix("https://github.com/")?;
+    let (owner, repo) = path.split_once('/')?;
+    let repo = repo.trim_end_matches(".git");
+    let octocrab = octocrab::OctocrabBuilder::new()
+        .personal_token(get_github_token()?)
+        .build()
+        .ok()?;
+
+    let releases = octocrab.repos(owner, repo)
+        .releases()
+        .list()
+        .per_page(10u8)
+        .send()
+        .await
+        .ok()?;
+    releases.items.into_iter()
+        .flat_map(|release| release.assets)
+        .find(|asset| asset.name == file_name)
+        .map(|asset| asset.browser_download_url.to_string())
+}
+
+fn resolved_mod(owner: &str, repo: &str, tag: String, asset: Asset) -> ResolvedMod {
+    ResolvedMod {
+        name: repo.to_string(),
+        version_name: tag.clone(),
+        update_info: ModUpdateMetadata::GitHub {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            tag,
+        },
+        file_name: asset.name,
+        download_url: asset.browser_download_url.to_string(),
+        mirror_urls: vec![],
+        mirror_repository: None,
+        hashes: None,
+        side: None,
+    }
+}
 
-        Ok(None)
+fn github_repository(update_info: &ModUpdateMetadata) -> (&str, &str, &str) {
+    match update_info {
+        ModUpdateMetadata::GitHub { owner, repo, tag } => (owner, repo, tag),
+        other => unreachable!("GitHub was asked about a mod from another source: {:?}", other),
     }
 }
 
+fn find_mod_asset(release: Release) -> Option<Asset> {
+    release.assets.into_iter()
+        .find(|asset| is_mod_jar(&asset.name))
+}
+
+/// Checks whether a file attached to a release is the mod itself, rather than a development or sources JAR.
+/// This is shared by every source that installs mods from releases, so they all pick the same file.
+pub fn is_mod_jar(name: &str) -> bool {
+    name.ends_with(".jar") && !(name.contains("-dev") || name.contains("-sources"))
+}
+
 pub fn get_github_token() -> Option<String> {
     std::env::vars().find(|(name, _)| name == "GITHUB_TOKEN").map(|(_, value)| value)
 }
+
+#[async_trait(?Send)]
+impl ModSource for GithubClient {
+    async fn resolve(&self, _pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        let (owner, repo) = spec.id.split_once('/')
+            .ok_or_else(|| SpecError::InvalidRepository(spec.id.clone()))?;
+        let resolved = match &spec.version {
+            Some(tag) => self.resolve_mod(owner, repo, tag).await?.map(|asset| (tag.clone(), asset)),
+            None => self.resolve_latest(owner, repo).await?,
+        };
+        Ok(resolved.map(|(tag, asset)| resolved_mod(owner, repo, tag, asset)))
+    }
+
+    async fn latest_compatible(&self, _pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (owner, repo, _) = github_repository(update_info);
+        Ok(self.resolve_latest(owner, repo).await?
+            .map(|(tag, asset)| resolved_mod(owner, repo, tag, asset)))
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (owner, repo, tag) = github_repository(update_info);
+        Ok(self.resolve_mod(owner, repo, tag).await?
+            .map(|asset| resolved_mod(owner, repo, tag.to_string(), asset)))
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (owner, repo, tag) = github_repository(update_info);
+        let repository: Repository = octocrab::instance()
+            .get(format!("/repos/{}/{}", owner, repo), None::<&()>)
+            .await?;
+        Ok(ModDescription {
+            title: repository.full_name,
+            authors: Some(repository.owner.login),
+            summary: repository.description,
+            url: Some(repository.html_url.to_string()),
+            version: tag.to_string(),
+        })
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{api_url, ModDescription, ModSource, ResolvedMod, SpecError};
+use crate::sources::github::is_mod_jar;
+use crate::util::{ensure_online, USER_AGENT};
+
+pub const GITLAB_HOST: &str = "https://gitlab.com";
+
+#[derive(Deserialize, Debug)]
+struct GitlabRelease {
+    tag_name: String,
+    assets: GitlabAssets,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabAssets {
+    #[serde(default)]
+    links: Vec<GitlabLink>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabLink {
+    name: String,
+    url: String,
+    #[serde(default)]
+    direct_asset_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabProject {
+    name_with_namespace: String,
+    #[serde(default)]
+    description: Option<String>,
+    web_url: String,
+    namespace: GitlabNamespace,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitlabNamespace {
+    name: String,
+}
+
+/// Releases on gitlab.com or a self-hosted GitLab instance. The files of a release are the links attached to it.
+pub struct GitlabClient {
+    client: Client,
+    token: Option<String>,
+}
+
+impl GitlabClient {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(USER_AGENT)
+                .build()?,
+            token: std::env::var("GITLAB_TOKEN").ok(),
+        })
+    }
+
+    fn get(&self, url: Url) -> RequestBuilder {
+        let request = self.client.get(url);
+        match &self.token {
+            Some(token) => request.header("PRIVATE-TOKEN", token),
+            None => request,
+        }
+    }
+
+    /// Calls the v4 API of a project, which is addressed by its whole path with the slashes escaped. GitLab answers
+    /// with a 404 for projects and releases that don't exist, which becomes `None`.
+    async fn project_api<T: DeserializeOwned>(&self, host: &str, project: &str, path: &[&str], query: &[(&str, &str)]) -> Result<Option<T>> {
+        ensure_online("GitLab")?;
+        let mut url = api_url(host, &[&["api", "v4", "projects", project], path].concat())?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        let response = self.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    /// Picks the mod JAR out of the links attached to a release. There is no endpoint for the latest release, so
+    /// without a tag the newest entry of the release list, which is sorted by date, is used instead.
+    async fn resolve_release(&self, host: &str, project: &str, tag: Option<&str>) -> Result<Option<ResolvedMod>> {
+        let release = match tag {
+            Some(tag) => self.project_api::<GitlabRelease>(host, project, &["releases", tag], &[]).await?,
+            None => self.project_api::<Vec<GitlabRelease>>(host, project, &["releases"], &[("per_page", "1")]).await?
+                .and_then(|releases| releases.into_iter().next()),
+        };
+        let release = match release {
+            None => return Ok(None),
+            Some(release) => release,
+        };
+
+        let link = match release.assets.links.into_iter().find(|link| is_mod_jar(&link.name)) {
+            None => return Ok(None),
+            Some(link) => link,
+        };
+        let name = project.rsplit('/').next().unwrap_or(project).to_string();
+        Ok(Some(ResolvedMod {
+            name,
+            version_name: release.tag_name.clone(),
+            update_info: ModUpdateMetadata::GitLab {
+                host: host.to_string(),
+                project: project.to_string(),
+                tag: release.tag_name,
+            },
+            file_name: link.name,
+            download_url: link.direct_asset_url.unwrap_or(link.url),
+            mirror_urls: vec![],
+            mirror_repository: None,
+            hashes: None,
+            side: None,
+        }))
+    }
+}
+
+fn gitlab_project(update_info: &ModUpdateMetadata) -> (&str, &str, &str) {
+    match update_info {
+        ModUpdateMetadata::GitLab { host, project, tag } => (host, project, tag),
+        other => unreachable!("GitLab was asked about a mod from another source: {:?}", other),
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for GitlabClient {
+    async fn resolve(&self, _pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        if !spec.id.contains('/') {
+            return Err(SpecError::InvalidProject(spec.id.clone()).into());
+        }
+        let host = spec.host.as_deref().unwrap_or(GITLAB_HOST);
+        self.resolve_release(host, &spec.id, spec.version.as_deref()).await
+    }
+
+    async fn latest_compatible(&self, _pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (host, project, _) = gitlab_project(update_info);
+        self.resolve_release(host, project, None).await
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (host, project, tag) = gitlab_project(update_info);
+        self.resolve_release(host, project, Some(tag)).await
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (host, project, tag) = gitlab_project(update_info);
+        let details: GitlabProject = self.project_api(host, project, &[], &[]).await?
+            .ok_or_else(|| SpecError::InvalidProject(project.to_string()))?;
+        Ok(ModDescription {
+            title: details.name_with_namespace,
+            authors: Some(details.namespace.name),
+            summary: details.description,
+            url: Some(details.web_url),
+            version: tag.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    const LATEST: &str = r#"[{
+        "tag_name": "v1.1",
+        "assets": {"links": [
+            {"name": "mod-1.1-sources.jar", "url": "https://example.com/mod-1.1-sources.jar"},
+            {"name": "mod-1.1.jar", "url": "https://example.com/uploads/mod-1.1.jar",
+             "direct_asset_url": "https://example.com/downloads/mod-1.1.jar"}
+        ]}
+    }]"#;
+
+    #[tokio::test]
+    async fn picks_the_mod_jar_from_the_latest_release() {
+        let host = test_server::serve(&[("/api/v4/projects/group%2Fmod/releases?per_page=1", LATEST)]).await;
+        let resolved = GitlabClient::new().unwrap().resolve_release(&host, "group/mod", None).await.unwrap().unwrap();
+        assert_eq!(resolved.name, "mod");
+        assert_eq!(resolved.file_name, "mod-1.1.jar");
+        assert_eq!(resolved.download_url, "https://example.com/downloads/mod-1.1.jar");
+        assert_eq!(resolved.update_info, ModUpdateMetadata::GitLab {
+            host: host.clone(),
+            project: "group/mod".to_string(),
+            tag: "v1.1".to_string(),
+        });
+    }
+
+    #[tokio::test]
+    async fn escapes_slashes_in_the_project_and_tag() {
+        let release = r#"{
+            "tag_name": "release/1.0",
+            "assets": {"links": [{"name": "mod-1.0.jar", "url": "https://example.com/uploads/mod-1.0.jar"}]}
+        }"#;
+        let host = test_server::serve(&[("/api/v4/projects/group%2Fsub%2Fmod/releases/release%2F1.0", release)]).await;
+        let resolved = GitlabClient::new().unwrap().resolve_release(&host, "group/sub/mod", Some("release/1.0")).await.unwrap().unwrap();
+        assert_eq!(resolved.name, "mod");
+        // without a direct asset URL, the link itself is used
+        assert_eq!(resolved.download_url, "https://example.com/uploads/mod-1.0.jar");
+        assert_eq!(resolved.version_name, "release/1.0");
+    }
+
+    #[tokio::test]
+    async fn missing_release_is_none() {
+        let host = test_server::serve(&[]).await;
+        let client = GitlabClient::new().unwrap();
+        assert!(client.resolve_release(&host, "group/mod", Some("v2.0")).await.unwrap().is_none());
+        assert!(client.resolve_release(&host, "group/mod", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn release_without_releases_or_jars_is_none() {
+        let host = test_server::serve(&[
+            ("/api/v4/projects/group%2Fempty/releases?per_page=1", "[]"),
+            ("/api/v4/projects/group%2Fmod/releases/v1.0", r#"{"tag_name": "v1.0", "assets": {"links": [
+                {"name": "mod-1.0-dev.jar", "url": "https://example.com/mod-1.0-dev.jar"}
+            ]}}"#),
+        ]).await;
+        let client = GitlabClient::new().unwrap();
+        assert!(client.resolve_release(&host, "group/empty", None).await.unwrap().is_none());
+        assert!(client.resolve_release(&host, "group/mod", Some("v1.0")).await.unwrap().is_none());
+    }
+}
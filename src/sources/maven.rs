@@ -0,0 +1,457 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use crate::hash::{FileHashes, HashAlgorithm};
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{ModDescription, ModSource, ResolvedMod, SpecError};
+use crate::util::{ensure_online, USER_AGENT};
+
+/// A `group:artifact:version[:classifier]` coordinate, as given to `maven-add`. The version can be `+` for the
+/// latest version, or a range like `[1.0,2.0)`.
+#[derive(Debug)]
+pub struct MavenCoordinate {
+    pub group: String,
+    pub artifact: String,
+    pub version: Option<String>,
+    pub classifier: Option<String>,
+}
+
+impl FromStr for MavenCoordinate {
+    type Err = SpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // ranges contain a comma but never a colon, so splitting on colons is safe
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() < 2 || parts.len() > 4 || parts.iter().any(|part| part.is_empty()) {
+            return Err(SpecError::InvalidCoordinate(s.to_string()));
+        }
+        Ok(Self {
+            group: parts[0].to_string(),
+            artifact: parts[1].to_string(),
+            version: parts.get(2).filter(|version| **version != "+").map(|version| version.to_string()),
+            classifier: parts.get(3).map(|classifier| classifier.to_string()),
+        })
+    }
+}
+
+/// Checks whether a version from pack.toml is a range rather than a single version.
+pub fn is_range(version: &str) -> bool {
+    version.starts_with(['[', '('])
+}
+
+/// A Maven version range with at most one lower and one upper bound, eg. `[1.0,2.0)` or `[1.2,)`.
+struct VersionRange {
+    lower: Option<(String, bool)>,
+    upper: Option<(String, bool)>,
+}
+
+impl FromStr for VersionRange {
+    type Err = SpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SpecError::InvalidVersionRange(s.to_string());
+        let lower_inclusive = match s.chars().next() {
+            Some('[') => true,
+            Some('(') => false,
+            _ => return Err(invalid()),
+        };
+        let upper_inclusive = match s.chars().last() {
+            Some(']') => true,
+            Some(')') => false,
+            _ => return Err(invalid()),
+        };
+        let inner = s.get(1..s.len() - 1).ok_or_else(invalid)?;
+        let bound = |version: &str, inclusive| Some(version.trim())
+            .filter(|version| !version.is_empty())
+            .map(|version| (version.to_string(), inclusive));
+
+        match inner.split_once(',') {
+            // `[1.0]` means exactly 1.0
+            None if lower_inclusive && upper_inclusive && !inner.trim().is_empty() => Ok(Self {
+                lower: bound(inner, true),
+                upper: bound(inner, true),
+            }),
+            None => Err(invalid()),
+            Some((_, upper)) if upper.contains(',') => Err(invalid()),
+            Some((lower, upper)) => Ok(Self {
+                lower: bound(lower, lower_inclusive),
+                upper: bound(upper, upper_inclusive),
+            }),
+        }
+    }
+}
+
+impl VersionRange {
+    fn contains(&self, version: &str) -> bool {
+        let above_lower = self.lower.as_ref().is_none_or(|(lower, inclusive)| match compare_versions(version, lower) {
+            Ordering::Greater => true,
+            Ordering::Equal => *inclusive,
+            Ordering::Less => false,
+        });
+        let below_upper = self.upper.as_ref().is_none_or(|(upper, inclusive)| match compare_versions(version, upper) {
+            Ordering::Less => true,
+            Ordering::Equal => *inclusive,
+            Ordering::Greater => false,
+        });
+        above_lower && below_upper
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum VersionPart<'a> {
+    // qualifiers like `beta` sort before numbers, so `1.0-beta` comes before `1.0.1`
+    Qualifier(&'a str),
+    Number(u64),
+}
+
+/// Compares versions roughly the way Maven does: numbers are compared as numbers, and a version with a qualifier
+/// (eg. `1.0-beta`) comes before the same version without one.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (version_parts(a), version_parts(b));
+    for i in 0..a.len().max(b.len()) {
+        let ordering = match (a.get(i), b.get(i)) {
+            (Some(a), Some(b)) => a.cmp(b),
+            // trailing zeros don't count, so `1.0` and `1.0.0` are the same version
+            (None, Some(VersionPart::Number(0))) | (Some(VersionPart::Number(0)), None) => Ordering::Equal,
+            // a release is newer than any of its pre-releases, but older than any later release
+            (None, Some(VersionPart::Qualifier(_))) => Ordering::Greater,
+            (Some(VersionPart::Qualifier(_)), None) => Ordering::Less,
+            (None, _) => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn version_parts(version: &str) -> Vec<VersionPart<'_>> {
+    version.split(['.', '-', '+', '_'])
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().map(VersionPart::Number).unwrap_or(VersionPart::Qualifier(part)))
+        .collect()
+}
+
+/// Reads the text of every `<tag>` element in a document. Maven metadata is simple enough that this is all
+/// that is needed to read it.
+fn xml_values<'a>(document: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    document.split(&open).skip(1)
+        .filter_map(|rest| rest.split_once(&close))
+        .map(|(value, _)| value.trim())
+        .collect()
+}
+
+/// An artifact in a Maven repository, without a version.
+struct Artifact<'a> {
+    repository: &'a str,
+    group: &'a str,
+    artifact: &'a str,
+    classifier: Option<&'a str>,
+}
+
+impl Artifact<'_> {
+    fn base_url(&self) -> String {
+        format!("{}/{}/{}", self.repository.trim_end_matches('/'), self.group.replace('.', "/"), self.artifact)
+    }
+
+    fn file_name(&self, version: &str) -> String {
+        match self.classifier {
+            Some(classifier) => format!("{}-{}-{}.jar", self.artifact, version, classifier),
+            None => format!("{}-{}.jar", self.artifact, version),
+        }
+    }
+
+    fn file_url(&self, version: &str) -> String {
+        format!("{}/{}/{}", self.base_url(), version, self.file_name(version))
+    }
+
+    fn update_info(&self, version: String, range: Option<String>) -> ModUpdateMetadata {
+        ModUpdateMetadata::Maven {
+            repository: self.repository.to_string(),
+            group: self.group.to_string(),
+            artifact: self.artifact.to_string(),
+            version,
+            classifier: self.classifier.map(String::from),
+            range,
+        }
+    }
+}
+
+/// Maven repositories, such as Fabric's or JitPack. They don't say which game versions an artifact supports, so
+/// the latest version (or the latest in a range) is assumed to be compatible.
+pub struct MavenSource {
+    client: Client,
+}
+
+impl MavenSource {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(USER_AGENT)
+                .build()?
+        })
+    }
+
+    async fn versions(&self, artifact: &Artifact<'_>) -> Result<Vec<String>> {
+        let url = format!("{}/maven-metadata.xml", artifact.base_url());
+        ensure_online(&url)?;
+        let metadata = self.client.get(&url).send().await?
+            .error_for_status()?
+            .text().await?;
+        Ok(xml_values(&metadata, "versions").into_iter()
+            .flat_map(|versions| xml_values(versions, "version"))
+            .map(String::from)
+            .collect())
+    }
+
+    /// The newest version of an artifact within `range`, leaving out snapshots.
+    async fn latest_version(&self, artifact: &Artifact<'_>, range: Option<&str>) -> Result<Option<String>> {
+        let range = range.map(VersionRange::from_str).transpose()?;
+        Ok(self.versions(artifact).await?.into_iter()
+            .filter(|version| !version.ends_with("-SNAPSHOT"))
+            .filter(|version| range.as_ref().is_none_or(|range| range.contains(version)))
+            .max_by(|a, b| compare_versions(a, b)))
+    }
+
+    /// Reads the checksum files published next to an artifact, so that it doesn't have to be downloaded to hash it.
+    /// Returns `None` if the artifact doesn't exist.
+    async fn artifact_version(&self, artifact: &Artifact<'_>, version: String, range: Option<String>) -> Result<Option<ResolvedMod>> {
+        let url = artifact.file_url(&version);
+        ensure_online(&url)?;
+        let mut hashes = FileHashes::default();
+        for algorithm in HashAlgorithm::ALL {
+            if let Some(hash) = self.checksum(&url, algorithm).await? {
+                hashes.insert(algorithm, hash);
+            }
+        }
+        if hashes.is_empty() && !self.client.head(&url).send().await?.status().is_success() {
+            return Ok(None);
+        }
+
+        Ok(Some(ResolvedMod {
+            name: artifact.artifact.to_string(),
+            version_name: version.clone(),
+            file_name: artifact.file_name(&version),
+            download_url: url,
+            mirror_urls: vec![],
+            mirror_repository: None,
+            hashes: Some(hashes).filter(|hashes| !hashes.is_empty()),
+            side: None,
+            update_info: artifact.update_info(version, range),
+        }))
+    }
+
+    async fn checksum(&self, url: &str, algorithm: HashAlgorithm) -> Result<Option<String>> {
+        let response = self.client.get(format!("{}.{}", url, algorithm.name())).send().await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        // some repositories write the file name after the hash, like sha1sum does
+        let text = response.text().await?;
+        Ok(text.split_whitespace().next()
+            .filter(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|hash| hash.to_lowercase()))
+    }
+
+    async fn resolve_latest(&self, artifact: &Artifact<'_>, range: Option<String>) -> Result<Option<ResolvedMod>> {
+        match self.latest_version(artifact, range.as_deref()).await? {
+            None => Ok(None),
+            Some(version) => self.artifact_version(artifact, version, range).await,
+        }
+    }
+}
+
+fn maven_artifact(update_info: &ModUpdateMetadata) -> (Artifact<'_>, &str, Option<&str>) {
+    match update_info {
+        ModUpdateMetadata::Maven { repository, group, artifact, version, classifier, range } => (Artifact {
+            repository,
+            group,
+            artifact,
+            classifier: classifier.as_deref(),
+        }, version, range.as_deref()),
+        other => unreachable!("Maven was asked about a mod from another source: {:?}", other),
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for MavenSource {
+    async fn resolve(&self, _pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        let repository = spec.repository.as_deref()
+            .ok_or_else(|| SpecError::MissingRepository(spec.id.clone()))?;
+        let (group, artifact) = spec.id.split_once(':')
+            .ok_or_else(|| SpecError::InvalidCoordinate(spec.id.clone()))?;
+        let artifact = Artifact {
+            repository,
+            group,
+            artifact,
+            classifier: spec.classifier.as_deref(),
+        };
+
+        match &spec.version {
+            Some(version) if !is_range(version) => self.artifact_version(&artifact, version.clone(), None).await,
+            range => self.resolve_latest(&artifact, range.clone()).await,
+        }
+    }
+
+    async fn latest_compatible(&self, _pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (artifact, _, range) = maven_artifact(update_info);
+        self.resolve_latest(&artifact, range.map(String::from)).await
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (artifact, version, range) = maven_artifact(update_info);
+        self.artifact_version(&artifact, version.to_string(), range.map(String::from)).await
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (artifact, version, _) = maven_artifact(update_info);
+        Ok(ModDescription {
+            title: format!("{}:{}", artifact.group, artifact.artifact),
+            authors: None,
+            summary: None,
+            url: Some(format!("{}/", artifact.base_url())),
+            version: version.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(range: &str) -> VersionRange {
+        range.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_coordinates() {
+        let coordinate: MavenCoordinate = "net.fabricmc:fabric-loader:0.14.0:sources".parse().unwrap();
+        assert_eq!(coordinate.group, "net.fabricmc");
+        assert_eq!(coordinate.artifact, "fabric-loader");
+        assert_eq!(coordinate.version.as_deref(), Some("0.14.0"));
+        assert_eq!(coordinate.classifier.as_deref(), Some("sources"));
+
+        let coordinate: MavenCoordinate = "com.example:mod:[1.0,2.0)".parse().unwrap();
+        assert_eq!(coordinate.version.as_deref(), Some("[1.0,2.0)"));
+        assert_eq!(coordinate.classifier, None);
+    }
+
+    #[test]
+    fn plus_or_no_version_means_latest() {
+        let coordinate: MavenCoordinate = "com.example:mod:+".parse().unwrap();
+        assert_eq!(coordinate.version, None);
+        let coordinate: MavenCoordinate = "com.example:mod".parse().unwrap();
+        assert_eq!(coordinate.version, None);
+    }
+
+    #[test]
+    fn rejects_invalid_coordinates() {
+        for coordinate in &["com.example", "com.example::1.0", "a:b:c:d:e", ""] {
+            assert!(coordinate.parse::<MavenCoordinate>().is_err(), "{} should be invalid", coordinate);
+        }
+    }
+
+    #[test]
+    fn detects_ranges() {
+        assert!(is_range("[1.0,2.0)"));
+        assert!(is_range("(,2.0)"));
+        assert!(!is_range("1.0"));
+    }
+
+    #[test]
+    fn exact_range() {
+        let exact = range("[1.0]");
+        assert!(exact.contains("1.0"));
+        assert!(exact.contains("1.0.0"));
+        assert!(!exact.contains("1.0.1"));
+        assert!(!exact.contains("0.9"));
+    }
+
+    #[test]
+    fn lower_bound_only() {
+        let at_least = range("[1.2,)");
+        assert!(at_least.contains("1.2"));
+        assert!(at_least.contains("1.2.0"));
+        assert!(at_least.contains("2.0"));
+        assert!(!at_least.contains("1.1.9"));
+        assert!(!at_least.contains("1.2-beta"));
+    }
+
+    #[test]
+    fn upper_bound_only() {
+        let below = range("(,2.0)");
+        assert!(below.contains("1.9.9"));
+        assert!(below.contains("0.1"));
+        assert!(!below.contains("2.0"));
+        assert!(!below.contains("2.0.0"));
+        assert!(below.contains("2.0-rc1"));
+    }
+
+    #[test]
+    fn bounded_range() {
+        let bounded = range("[1.0,2.0)");
+        assert!(bounded.contains("1.0"));
+        assert!(bounded.contains("1.5.3"));
+        assert!(!bounded.contains("2.0"));
+        let exclusive = range("(1.0,2.0]");
+        assert!(!exclusive.contains("1.0.0"));
+        assert!(exclusive.contains("2.0.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for invalid in &["1.0", "[1.0", "1.0]", "(1.0)", "[1.0,2.0,3.0]", "[]"] {
+            assert!(invalid.parse::<VersionRange>().is_err(), "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn compares_numbers_as_numbers() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.1", "1.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn ignores_trailing_zeros() {
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.0-beta", "1.0"), Ordering::Less);
+    }
+
+    #[test]
+    fn pre_releases_come_before_releases() {
+        assert_eq!(compare_versions("1.0-beta", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0-rc1"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0-beta", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0-alpha", "1.0-beta"), Ordering::Less);
+    }
+
+    #[test]
+    fn reads_versions_from_metadata() {
+        let metadata = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>com.example</groupId>
+  <artifactId>mod</artifactId>
+  <versioning>
+    <latest>2.0-SNAPSHOT</latest>
+    <release>1.1</release>
+    <versions>
+      <version>1.0</version>
+      <version> 1.1 </version>
+      <version>2.0-SNAPSHOT</version>
+    </versions>
+  </versioning>
+</metadata>"#;
+        let versions: Vec<&str> = xml_values(metadata, "versions").into_iter()
+            .flat_map(|versions| xml_values(versions, "version"))
+            .collect();
+        assert_eq!(versions, vec!["1.0", "1.1", "2.0-SNAPSHOT"]);
+        assert_eq!(xml_values(metadata, "release"), vec!["1.1"]);
+        assert!(xml_values(metadata, "lastUpdated").is_empty());
+    }
+}
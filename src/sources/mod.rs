@@ -1,9 +1,169 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Url;
+use crate::hash::FileHashes;
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata, ModSide, ModSpec, SourceKind};
+use crate::sources::curseforge::CurseforgeClient;
+use crate::sources::gitea::GiteaClient;
+use crate::sources::github::GithubClient;
+use crate::sources::gitlab::GitlabClient;
+use crate::sources::maven::MavenSource;
+use crate::sources::modrinth::ModrinthSource;
+use crate::sources::url::UrlSource;
+use crate::util::{hash_from_url, info};
+
 pub mod curseforge;
 pub mod modrinth;
 pub mod github;
+pub mod gitlab;
+pub mod gitea;
+pub mod url;
+pub mod maven;
 
 #[derive(thiserror::Error, Debug)]
 pub enum ResolutionError {
     #[error("unknown slug: {0}")]
     UnknownSlug(String),
+    #[error("unknown id: {0}")]
+    UnknownId(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SpecError {
+    #[error("invalid GitHub repository: {0} (expected owner/repo)")]
+    InvalidRepository(String),
+    #[error("invalid CurseForge file ID: {0}")]
+    InvalidFileId(String),
+    #[error("invalid Maven coordinate: {0} (expected group:artifact:version[:classifier])")]
+    InvalidCoordinate(String),
+    #[error("invalid Maven version range: {0}")]
+    InvalidVersionRange(String),
+    #[error("{0} is from Maven, but has no repository set")]
+    MissingRepository(String),
+    #[error("invalid project: {0} (expected group/project)")]
+    InvalidProject(String),
+    #[error("invalid host: {0}")]
+    InvalidHost(String),
+}
+
+/// A service that mods can be added from, and later updated through.
+#[async_trait(?Send)]
+pub trait ModSource {
+    /// Resolves a mod from pack.toml to the version it pins, or to the latest compatible version if it isn't pinned.
+    async fn resolve(&self, pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>>;
+
+    /// Finds the latest version of a locked mod that is compatible with the pack. Sources that can't tell which game
+    /// versions a file supports, such as releases and Maven repositories, treat their newest version as compatible.
+    async fn latest_compatible(&self, pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>>;
+
+    /// Fetches exactly the version of a mod that `update_info` points at.
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>>;
+
+    /// Looks up what the mod is, and the name of the version that `update_info` points at.
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription>;
+}
+
+pub struct ModDescription {
+    pub title: String,
+    pub authors: Option<String>,
+    pub summary: Option<String>,
+    pub url: Option<String>,
+    pub version: String,
+}
+
+/// A version of a mod that a source has found, before it is hashed and turned into a [`ModMetadata`].
+pub struct ResolvedMod {
+    /// The slug or name of the mod, which is the name it is added to the pack under unless one is given.
+    pub name: String,
+    /// The human-readable name of the version.
+    pub version_name: String,
+    pub update_info: ModUpdateMetadata,
+    pub file_name: String,
+    pub download_url: String,
+    pub mirror_urls: Vec<String>,
+    /// A repository whose GitHub releases might have the same file. It is only looked up once the metadata is
+    /// written, so that checking for updates doesn't use up the GitHub rate limit.
+    pub mirror_repository: Option<String>,
+    /// The hashes supplied by the source. If there are none, the file is downloaded and hashed.
+    pub hashes: Option<FileHashes>,
+    /// The side the source says the mod is needed on, if it says.
+    pub side: Option<ModSide>,
+}
+
+impl ResolvedMod {
+    pub async fn into_metadata(self) -> Result<ModMetadata> {
+        let hashes = match self.hashes {
+            Some(hashes) => hashes,
+            None => {
+                info(&format!("Hashing {}...", self.file_name));
+                hash_from_url(&self.download_url).await?
+            }
+        };
+        let mut mirror_urls = self.mirror_urls;
+        if let Some(repository) = &self.mirror_repository {
+            mirror_urls.extend(github::find_release_asset(repository, &self.file_name).await);
+        }
+        Ok(ModMetadata {
+            name: self.name,
+            download_url: self.download_url,
+            mirror_urls,
+            output_path: format!("./mods/{}", self.file_name),
+            side: self.side.unwrap_or_default(),
+            hashes,
+            update_info: Some(self.update_info),
+        })
+    }
+}
+
+/// Builds the URL of an API endpoint on a self-hostable service, escaping each part of the path.
+pub fn api_url(host: &str, segments: &[&str]) -> Result<Url> {
+    let mut url = Url::parse(host).map_err(|_| SpecError::InvalidHost(host.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|_| SpecError::InvalidHost(host.to_string()))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// Every source that mods can be added from, so that commands can work with a mod without caring where it is from.
+pub struct Sources {
+    curseforge: CurseforgeClient,
+    modrinth: ModrinthSource,
+    github: Option<GithubClient>,
+    url: UrlSource,
+    maven: MavenSource,
+    gitlab: GitlabClient,
+    gitea: GiteaClient,
+}
+
+impl Sources {
+    /// The GitHub client is optional, as creating it warns about missing tokens; it only needs to be given if a
+    /// GitHub mod will be looked up.
+    pub fn new(github_client: Option<GithubClient>) -> Result<Self> {
+        Ok(Self {
+            curseforge: CurseforgeClient::new()?,
+            modrinth: ModrinthSource::new()?,
+            github: github_client,
+            url: UrlSource::new()?,
+            maven: MavenSource::new()?,
+            gitlab: GitlabClient::new()?,
+            gitea: GiteaClient::new()?,
+        })
+    }
+
+    pub fn for_kind(&self, kind: &SourceKind) -> &dyn ModSource {
+        match kind {
+            SourceKind::Curseforge => &self.curseforge,
+            SourceKind::Modrinth => &self.modrinth,
+            SourceKind::GitHub => self.github.as_ref().expect("GitHub client was not initialised"),
+            SourceKind::Url => &self.url,
+            SourceKind::Maven => &self.maven,
+            SourceKind::GitLab => &self.gitlab,
+            SourceKind::Gitea => &self.gitea,
+        }
+    }
+
+    pub fn for_update_info(&self, update_info: &ModUpdateMetadata) -> &dyn ModSource {
+        self.for_kind(&update_info.source_kind())
+    }
 }
@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use reqwest::Client;
 use anyhow::Result;
-use crate::util::USER_AGENT;
+use async_trait::async_trait;
+use crate::hash::FileHashes;
+use crate::pack::{ModPack, ModSide, ModSpec, ModUpdateMetadata};
+use crate::sources::{ModDescription, ModSource, ResolvedMod};
+use crate::util::{ensure_online, USER_AGENT};
 use crate::util::error;
 
 const MODRINTH_API: &str = "https://api.modrinth.com/api/v1";
@@ -14,7 +18,27 @@ pub struct ModrinthMod {
     pub id: String,
     pub slug: String,
     pub title: String,
+    #[serde(default)]
+    pub description: String,
     pub versions: Vec<String>,
+    #[serde(default)]
+    pub client_side: String,
+    #[serde(default)]
+    pub server_side: String,
+    #[serde(default)]
+    pub source_url: Option<String>,
+}
+
+impl ModrinthMod {
+    /// Which side the mod is needed on, going by whether it declares either side as unsupported.
+    pub fn side(&self) -> ModSide {
+        match (self.client_side.as_str(), self.server_side.as_str()) {
+            ("unsupported", "unsupported") => ModSide::Both,
+            ("unsupported", _) => ModSide::Server,
+            (_, "unsupported") => ModSide::Client,
+            _ => ModSide::Both,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -26,6 +50,8 @@ pub struct ModrinthVersion {
     pub game_versions: Vec<String>,
     pub loaders: Vec<String>,
     pub date_published: DateTime<Utc>,
+    #[serde(default)]
+    pub dependencies: Vec<ModrinthDependency>,
 }
 
 impl ModrinthVersion {
@@ -43,6 +69,55 @@ pub struct ModrinthVersionFile {
     pub hashes: HashMap<String, String>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct ModrinthDependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    pub dependency_type: ModrinthDependencyType,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModrinthDependencyType {
+    Required,
+    Optional,
+    Incompatible,
+    Embedded,
+}
+
+pub type ResolvedModrinthVersion = (ModrinthMod, ModrinthVersion, ModrinthVersionFile);
+
+pub fn resolved_mod(staging: Option<bool>, (mod_data, version, file): ResolvedModrinthVersion) -> ResolvedMod {
+    ResolvedMod {
+        side: Some(mod_data.side()),
+        mirror_urls: vec![],
+        // if the source code is on GitHub, its releases might have the same file
+        mirror_repository: mod_data.source_url.clone(),
+        name: mod_data.slug,
+        version_name: version.name,
+        update_info: ModUpdateMetadata::Modrinth {
+            project_id: version.mod_id,
+            version_id: version.id,
+            staging,
+        },
+        file_name: file.filename,
+        download_url: file.url,
+        hashes: Some(FileHashes::from_map(&file.hashes)),
+    }
+}
+
+#[derive(Default)]
+pub struct ModrinthDependencies {
+    /// Required dependencies, resolved recursively.
+    pub required: Vec<ResolvedModrinthVersion>,
+    /// Optional dependencies. Their own dependencies have not been resolved yet.
+    pub optional: Vec<ResolvedModrinthVersion>,
+    /// Mods declared as incompatible that are already present.
+    pub incompatible: Vec<ModrinthMod>,
+    /// Required dependencies that have no compatible version.
+    pub unresolved: Vec<ModrinthMod>,
+}
+
 pub struct ModrinthClient {
     staging: bool,
     client: Client,
@@ -61,19 +136,19 @@ impl ModrinthClient {
 
     pub async fn resolve_mod(&self, identifier: &str, predicate: &dyn Fn(ModrinthVersion) -> bool)
         -> Result<Option<(ModrinthMod, ModrinthVersion, ModrinthVersionFile)>> {
-        return if identifier.contains(':') { // User has specified a version ID, check that it actually exists for the specified mod.
+        if identifier.contains(':') { // User has specified a version ID, check that it actually exists for the specified mod.
             let vec: Vec<&str> = identifier.split(':').collect();
             if vec.len() != 2 {
-                error(&*format!("Invalid version specifier: {} (too many colons)", identifier));
+                error(&format!("Invalid version specifier: {} (too many colons)", identifier));
                 Ok(None)
             } else {
-                let mod_details = self.get_mod(&vec[0].to_string()).await?;
+                let mod_details = self.get_mod(vec[0]).await?;
                 let version_id = vec[1].to_string();
-                return if mod_details.versions.contains(&version_id) {
+                if mod_details.versions.contains(&version_id) {
                     let version = self.get_version(&version_id).await?;
                     Ok(version.resolve_file().map(|f| (mod_details, version, f)))
                 } else {
-                    error(&*format!("Invalid version for mod {}: {}", mod_details.title, vec[1]));
+                    error(&format!("Invalid version for mod {}: {}", mod_details.title, vec[1]));
                     Ok(None)
                 }
             }
@@ -83,15 +158,85 @@ impl ModrinthClient {
 
             let mut filtered_versions = versions.iter().filter(|&v| predicate(v.clone())).collect::<Vec<&ModrinthVersion>>();
 
-            filtered_versions.sort_by(|&v1, &v2| v1.date_published.cmp(&v2.date_published));
+            filtered_versions.sort_by_key(|&v1| v1.date_published);
 
-            return match filtered_versions.last() {
+            match filtered_versions.last() {
                 None => Ok(None),
                 Some(&version) => Ok(version.resolve_file().map(|f| (details, version.clone(), f)))
             }
         }
     }
 
+    /// Walks the dependency tree of the given versions. Projects for which `is_present` returns true
+    /// are treated as already satisfied and are not resolved again.
+    pub async fn resolve_dependencies(&self, versions: &[ModrinthVersion], predicate: &dyn Fn(ModrinthVersion) -> bool,
+                                      is_present: &dyn Fn(&str) -> bool) -> Result<ModrinthDependencies> {
+        let mut result = ModrinthDependencies::default();
+        let mut seen: HashSet<String> = versions.iter().map(|v| v.mod_id.clone()).collect();
+        let mut incompatible_ids = vec![];
+        let mut queue: VecDeque<ModrinthDependency> = versions.iter()
+            .flat_map(|v| v.dependencies.iter().cloned())
+            .collect();
+
+        while let Some(dependency) = queue.pop_front() {
+            let project_id = match (&dependency.project_id, &dependency.version_id) {
+                (Some(project_id), _) => project_id.clone(),
+                (None, Some(version_id)) => self.get_version(version_id).await?.mod_id,
+                (None, None) => continue,
+            };
+
+            match dependency.dependency_type {
+                ModrinthDependencyType::Embedded => continue,
+                ModrinthDependencyType::Incompatible => {
+                    incompatible_ids.push(project_id);
+                    continue;
+                }
+                ModrinthDependencyType::Optional => {
+                    if is_present(&project_id) || seen.contains(&project_id)
+                        || result.optional.iter().any(|(m, _, _)| m.id == project_id) {
+                        continue;
+                    }
+                }
+                ModrinthDependencyType::Required => {
+                    if is_present(&project_id) || !seen.insert(project_id.clone()) {
+                        continue;
+                    }
+                    // a required dependency takes precedence over the same project being offered as optional
+                    result.optional.retain(|(m, _, _)| m.id != project_id);
+                }
+            }
+
+            let resolved = match &dependency.version_id {
+                Some(version_id) => {
+                    let version = self.get_version(version_id).await?;
+                    let mod_details = self.get_mod(&project_id).await?;
+                    version.resolve_file().map(|f| (mod_details, version, f))
+                }
+                None => self.resolve_mod(&project_id, predicate).await?,
+            };
+
+            match resolved {
+                None => if dependency.dependency_type == ModrinthDependencyType::Required {
+                    result.unresolved.push(self.get_mod(&project_id).await?);
+                }
+                Some(resolved) => if dependency.dependency_type == ModrinthDependencyType::Required {
+                    queue.extend(resolved.1.dependencies.iter().cloned());
+                    result.required.push(resolved);
+                } else {
+                    result.optional.push(resolved);
+                }
+            }
+        }
+
+        for project_id in incompatible_ids {
+            if is_present(&project_id) || seen.contains(&project_id) {
+                result.incompatible.push(self.get_mod(&project_id).await?);
+            }
+        }
+
+        Ok(result)
+    }
+
     fn get_api_base(&self) -> &str {
         if self.staging {
             MODRINTH_STAGING_API
@@ -100,21 +245,99 @@ impl ModrinthClient {
         }
     }
 
-    async fn get_mod(&self, slug: &str) -> Result<ModrinthMod> {
+    pub async fn get_mod(&self, slug: &str) -> Result<ModrinthMod> {
+        ensure_online("Modrinth")?;
         Ok(self.client.get(format!("{}/mod/{}", self.get_api_base(), slug))
             .send().await?
             .json().await?)
     }
 
     async fn get_mod_versions(&self, mod_id: &str) -> Result<Vec<ModrinthVersion>> {
+        ensure_online("Modrinth")?;
         Ok(self.client.get(format!("{}/mod/{}/version", self.get_api_base(), mod_id))
             .send().await?
             .json().await?)
     }
 
-    async fn get_version(&self, version_id: &str) -> Result<ModrinthVersion> {
+    pub async fn get_version(&self, version_id: &str) -> Result<ModrinthVersion> {
+        ensure_online("Modrinth")?;
         Ok(self.client.get(format!("{}/version/{}", self.get_api_base(), version_id))
             .send().await?
             .json().await?)
     }
 }
+
+/// Modrinth as a [`ModSource`], which can look mods up on either the live or the staging API.
+pub struct ModrinthSource {
+    client: ModrinthClient,
+    staging_client: ModrinthClient,
+}
+
+impl ModrinthSource {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: ModrinthClient::new(false)?,
+            staging_client: ModrinthClient::new(true)?,
+        })
+    }
+
+    fn client(&self, staging: Option<bool>) -> &ModrinthClient {
+        if staging.unwrap_or(false) {
+            &self.staging_client
+        } else {
+            &self.client
+        }
+    }
+
+    async fn resolve_identifier(&self, pack: &ModPack, staging: Option<bool>, identifier: &str) -> Result<Option<ResolvedMod>> {
+        Ok(self.client(staging).resolve_mod(identifier, &|v| pack.supports(&v)).await?
+            .map(|resolved| resolved_mod(staging, resolved)))
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for ModrinthSource {
+    async fn resolve(&self, pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        let identifier = match &spec.version {
+            Some(version_id) => format!("{}:{}", spec.id, version_id),
+            None => spec.id.clone(),
+        };
+        self.resolve_identifier(pack, spec.staging, &identifier).await
+    }
+
+    async fn latest_compatible(&self, pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (project_id, _, staging) = modrinth_ids(update_info);
+        self.resolve_identifier(pack, staging, project_id).await
+    }
+
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (project_id, version_id, staging) = modrinth_ids(update_info);
+        let client = self.client(staging);
+        let mod_data = client.get_mod(project_id).await?;
+        let version = client.get_version(version_id).await?;
+        Ok(version.resolve_file()
+            .map(|file| resolved_mod(staging, (mod_data, version, file))))
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (project_id, version_id, staging) = modrinth_ids(update_info);
+        let client = self.client(staging);
+        let mod_data = client.get_mod(project_id).await?;
+        Ok(ModDescription {
+            version: client.get_version(version_id).await
+                .map(|v| v.name)
+                .unwrap_or_else(|_| version_id.to_string()),
+            authors: None,
+            url: Some(format!("https://modrinth.com/mod/{}", mod_data.slug)),
+            title: mod_data.title,
+            summary: Some(mod_data.description),
+        })
+    }
+}
+
+fn modrinth_ids(update_info: &ModUpdateMetadata) -> (&str, &str, Option<bool>) {
+    match update_info {
+        ModUpdateMetadata::Modrinth { project_id, version_id, staging } => (project_id, version_id, *staging),
+        other => unreachable!("Modrinth was asked about a mod from another source: {:?}", other),
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Response, StatusCode};
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use crate::hash::{FileHasher, HashAlgorithm};
+use crate::pack::{ModPack, ModSpec, ModUpdateMetadata};
+use crate::sources::{ModDescription, ModSource, ResolvedMod};
+use crate::util::{ensure_online, USER_AGENT};
+
+/// Files hosted anywhere else. There is no way to ask for a compatible version, so the file at the URL is always
+/// used, and the `ETag` and `Last-Modified` headers the server sends are used to tell when it has changed.
+pub struct UrlSource {
+    client: Client,
+}
+
+impl UrlSource {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(USER_AGENT)
+                .build()?
+        })
+    }
+
+    /// Downloads the file at `url` to hash it, unless the server says it has not changed since `etag` and
+    /// `last_modified` were recorded.
+    async fn fetch(&self, url: &str, etag: Option<&str>, last_modified: Option<&str>) -> Result<ResolvedMod> {
+        let (resolved, response) = self.request(url, etag, last_modified).await?;
+        self.hash(resolved, response).await
+    }
+
+    /// Asks for the file at `url`, returning what the headers say about it along with the response to read the file
+    /// from. There is no response if the server says the file has not changed.
+    pub async fn request(&self, url: &str, etag: Option<&str>, last_modified: Option<&str>) -> Result<(ResolvedMod, Option<Response>)> {
+        ensure_online(url)?;
+        let mut request = self.client.get(url);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok((resolved_mod(url, file_name(&response), etag.map(String::from), last_modified.map(String::from)), None));
+        }
+        let response = response.error_for_status()?;
+        let resolved = resolved_mod(url, file_name(&response), header(response.headers(), ETAG), header(response.headers(), LAST_MODIFIED));
+        Ok((resolved, Some(response)))
+    }
+
+    /// Downloads the body of a response to fill in the hashes of the file it resolved to.
+    pub async fn hash(&self, mut resolved: ResolvedMod, response: Option<Response>) -> Result<ResolvedMod> {
+        let response = match response {
+            None => return Ok(resolved),
+            Some(response) => response,
+        };
+        let mut hasher = FileHasher::new(&[HashAlgorithm::Sha1, HashAlgorithm::Sha512]);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            hasher.update(&chunk?);
+        }
+        resolved.hashes = Some(hasher.finish());
+        Ok(resolved)
+    }
+}
+
+fn resolved_mod(url: &str, file_name: String, etag: Option<String>, last_modified: Option<String>) -> ResolvedMod {
+    let update_info = ModUpdateMetadata::Url {
+        url: url.to_string(),
+        etag,
+        last_modified,
+    };
+    ResolvedMod {
+        name: file_name.strip_suffix(".jar").unwrap_or(&file_name).to_string(),
+        version_name: update_info.version(),
+        update_info,
+        file_name,
+        download_url: url.to_string(),
+        mirror_urls: vec![],
+        mirror_repository: None,
+        hashes: None,
+        side: None,
+    }
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(String::from)
+}
+
+/// The name the server gives the file in `Content-Disposition`, or else the last part of the URL it came from.
+fn file_name(response: &Response) -> String {
+    header(response.headers(), CONTENT_DISPOSITION)
+        .and_then(|disposition| disposition_file_name(&disposition))
+        .or_else(|| url_file_name(response.url().as_str()))
+        .unwrap_or_else(|| "download.jar".to_string())
+}
+
+fn disposition_file_name(disposition: &str) -> Option<String> {
+    let parameters: Vec<(&str, &str)> = disposition.split(';')
+        .filter_map(|parameter| parameter.trim().split_once('='))
+        .collect();
+    // filename* is the UTF-8 version, and is preferred over the plain filename when both are sent
+    let file_name = parameters.iter().find(|(key, _)| key.eq_ignore_ascii_case("filename*"))
+        .and_then(|(_, value)| extended_value(value))
+        .or_else(|| parameters.iter().find(|(key, _)| key.eq_ignore_ascii_case("filename"))
+            .map(|(_, value)| value.trim_matches('"').to_string()))?;
+    safe_file_name(&file_name)
+}
+
+/// Decodes an RFC 5987 value such as `UTF-8''my%20mod.jar`, giving up on anything that isn't valid UTF-8.
+fn extended_value(value: &str) -> Option<String> {
+    let value = value.trim_matches('"');
+    let encoded = value.split_once("''").map_or(value, |(_, encoded)| encoded);
+    percent_decode_str(encoded).decode_utf8().ok().map(String::from)
+}
+
+fn url_file_name(url: &str) -> Option<String> {
+    url.split(['?', '#']).next()
+        .and_then(|url| url.rsplit('/').next())
+        .and_then(safe_file_name)
+}
+
+/// Keeps only the last component of a file name, so that a server can't choose where the file is saved.
+fn safe_file_name(file_name: &str) -> Option<String> {
+    file_name.rsplit(['/', '\\']).next()
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+        .map(String::from)
+}
+
+fn url_validators(update_info: &ModUpdateMetadata) -> (&str, Option<&str>, Option<&str>) {
+    match update_info {
+        ModUpdateMetadata::Url { url, etag, last_modified } => (url, etag.as_deref(), last_modified.as_deref()),
+        other => unreachable!("the URL source was asked about a mod from another source: {:?}", other),
+    }
+}
+
+#[async_trait(?Send)]
+impl ModSource for UrlSource {
+    async fn resolve(&self, _pack: &ModPack, spec: &ModSpec) -> Result<Option<ResolvedMod>> {
+        self.fetch(&spec.id, None, None).await.map(Some)
+    }
+
+    async fn latest_compatible(&self, _pack: &ModPack, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (url, etag, last_modified) = url_validators(update_info);
+        self.fetch(url, etag, last_modified).await.map(Some)
+    }
+
+    // A URL only ever serves its current file, so this is the same as checking for the latest one
+    async fn fetch_pinned(&self, update_info: &ModUpdateMetadata) -> Result<Option<ResolvedMod>> {
+        let (url, _, _) = url_validators(update_info);
+        self.fetch(url, None, None).await.map(Some)
+    }
+
+    async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        let (url, _, _) = url_validators(update_info);
+        Ok(ModDescription {
+            title: url_file_name(url).unwrap_or_else(|| url.to_string()),
+            authors: None,
+            summary: None,
+            url: Some(url.to_string()),
+            version: update_info.version(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_plain_file_name() {
+        assert_eq!(disposition_file_name("attachment; filename=\"my mod.jar\"").as_deref(), Some("my mod.jar"));
+        assert_eq!(disposition_file_name("attachment; filename=mod.jar").as_deref(), Some("mod.jar"));
+        assert_eq!(disposition_file_name("inline"), None);
+    }
+
+    #[test]
+    fn decodes_the_extended_file_name() {
+        assert_eq!(disposition_file_name("attachment; filename*=UTF-8''my%20mod.jar").as_deref(), Some("my mod.jar"));
+        assert_eq!(disposition_file_name("attachment; filename*=UTF-8''caf%C3%A9.jar").as_deref(), Some("café.jar"));
+        assert_eq!(disposition_file_name("attachment; filename=\"fallback.jar\"; filename*=UTF-8''real.jar").as_deref(), Some("real.jar"));
+    }
+
+    #[test]
+    fn falls_back_when_the_extended_file_name_is_not_utf8() {
+        let disposition = "attachment; filename=\"cafe.jar\"; filename*=ISO-8859-1''caf%E9.jar";
+        assert_eq!(disposition_file_name(disposition).as_deref(), Some("cafe.jar"));
+    }
+
+    #[test]
+    fn keeps_only_the_last_path_component() {
+        assert_eq!(disposition_file_name("attachment; filename*=UTF-8''..%2F..%2Fevil.jar").as_deref(), Some("evil.jar"));
+        assert_eq!(disposition_file_name("attachment; filename=\"../\"").as_deref(), None);
+        assert_eq!(url_file_name("https://example.com/files/mod.jar?download=1").as_deref(), Some("mod.jar"));
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+/// Keeps track of the files that pack-it has installed, so that they can be cleaned up once they leave the pack.
+/// Stored in `.pack-it/installed.toml` next to `pack.toml`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct InstallState {
+    #[serde(default)]
+    installed_files: BTreeSet<String>,
+}
+
+impl InstallState {
+    pub fn read(pack_file: &Path) -> Result<Self> {
+        let state_file = state_file_path(pack_file);
+        if !state_file.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(state_file)?)?)
+    }
+
+    pub fn save(&self, pack_file: &Path) -> Result<()> {
+        let state_file = state_file_path(pack_file);
+        if let Some(parent) = state_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(state_file, toml::to_string(&self)?)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, path: &str) {
+        self.installed_files.insert(normalize_path(path));
+    }
+
+    pub fn forget(&mut self, path: &str) {
+        self.installed_files.remove(&normalize_path(path));
+    }
+
+    pub fn is_managed(&self, path: &str) -> bool {
+        self.installed_files.contains(&normalize_path(path))
+    }
+
+    pub fn installed_files(&self) -> Vec<String> {
+        self.installed_files.iter().cloned().collect()
+    }
+}
+
+fn state_file_path(pack_file: &Path) -> PathBuf {
+    pack_file.parent().unwrap_or_else(|| Path::new("")).join(".pack-it").join("installed.toml")
+}
+
+/// Normalizes an output path so that `./mods/a.jar` and `mods/a.jar` are treated as the same file.
+pub fn normalize_path(path: &str) -> String {
+    Path::new(path).components()
+        .filter(|c| *c != Component::CurDir)
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A stand-in for a self-hosted API, answering each request target with a canned JSON body and anything else with a
+/// 404. Returns the URL to use as the host.
+pub async fn serve(routes: &[(&str, &str)]) -> String {
+    let routes: Vec<(String, String)> = routes.iter()
+        .map(|(target, body)| (target.to_string(), body.to_string()))
+        .collect();
+    let (listener, url) = bind().await;
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let request = read_request(&mut stream).await;
+            let target = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match routes.iter().find(|(route, _)| route == target) {
+                Some((_, body)) => ("200 OK", body.as_str()),
+                None => ("404 Not Found", r#"{"message":"404 Not Found"}"#),
+            };
+            let response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                   status, body.len(), body);
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    url
+}
+
+/// Answers each connection with the next of `responses`, written out as-is, so that tests can send broken or
+/// unusual responses. Returns the URL of the server and the requests it has received so far.
+pub async fn serve_responses(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let (listener, url) = bind().await;
+    let requests = Arc::new(Mutex::new(vec![]));
+    let received = requests.clone();
+
+    tokio::spawn(async move {
+        for response in responses {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(_) => return,
+            };
+            let request = read_request(&mut stream).await;
+            received.lock().unwrap().push(request);
+            let _ = stream.write_all(&response).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    (url, requests)
+}
+
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind test server");
+    let address = listener.local_addr().expect("Test server has no address");
+    (listener, format!("http://{}", address))
+}
+
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = vec![];
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    String::from_utf8_lossy(&request).to_string()
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+use anyhow::Result;
+use crate::pack::{ModPack, ModMetadata, ModUpdateMetadata};
+use crate::sources::{ModDescription, ResolvedMod, Sources};
+use crate::sources::github::GithubClient;
+
+pub enum UpdateCheck {
+    UpToDate,
+    Available {
+        from: String,
+        resolved: Box<ResolvedMod>,
+    },
+    NoCompatibleVersion,
+}
+
+pub struct Updater {
+    sources: Sources,
+}
+
+impl Updater {
+    pub fn new(github_client: Option<GithubClient>) -> Result<Self> {
+        Ok(Self {
+            sources: Sources::new(github_client)?,
+        })
+    }
+
+    /// Re-resolves a mod through the source it was added from, returning the newer compatible version if there
+    /// is one. Nothing is downloaded until the update is applied, unless the source needs to download the file to
+    /// tell whether it has changed.
+    pub async fn check(&self, pack: &ModPack, current: &ModMetadata, update_info: &ModUpdateMetadata) -> Result<UpdateCheck> {
+        let source = self.sources.for_update_info(update_info);
+        let resolved = match source.latest_compatible(pack, update_info).await? {
+            None => return Ok(UpdateCheck::NoCompatibleVersion),
+            Some(resolved) => resolved,
+        };
+        // when the source gives hashes, they say whether the file itself has changed
+        let up_to_date = match &resolved.hashes {
+            Some(hashes) => hashes.matches(&current.hashes),
+            None => resolved.update_info == *update_info,
+        };
+        if up_to_date {
+            return Ok(UpdateCheck::UpToDate);
+        }
+
+        let from = source.describe(update_info).await
+            .map(|description| description.version)
+            .unwrap_or_else(|_| update_info.version());
+        Ok(UpdateCheck::Available {
+            from,
+            resolved: Box::new(resolved),
+        })
+    }
+
+    pub async fn describe(&self, update_info: &ModUpdateMetadata) -> Result<ModDescription> {
+        self.sources.for_update_info(update_info).describe(update_info).await
+    }
+}
+
+/// Turns an update into the new metadata for a mod, keeping the name, directory and side it already had unless
+/// the source says which side it is for.
+pub async fn apply_update(current: &ModMetadata, resolved: ResolvedMod) -> Result<ModMetadata> {
+    let side = resolved.side.unwrap_or(current.side);
+    let file_name = resolved.file_name.clone();
+    let mut metadata = resolved.into_metadata().await?;
+    metadata.name = current.name.clone();
+    metadata.output_path = replace_file_name(&current.output_path, &file_name);
+    metadata.side = side;
+    Ok(metadata)
+}
+
+/// Swaps the file name of an output path, keeping the directory the user chose for it.
+fn replace_file_name(output_path: &str, file_name: &str) -> String {
+    Path::new(output_path).with_file_name(file_name).to_string_lossy().to_string()
+}
@@ -1,6 +1,7 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use anyhow::Result;
+use crate::hash::{FileHasher, FileHashes, HashAlgorithm};
 use colored::Colorize;
-use sha1::Digest;
 use terminal_size::{terminal_size, Width};
 
 pub const USER_AGENT: &str = concat!(
@@ -10,6 +11,28 @@ pub const USER_AGENT: &str = concat!(
     " (https://github.com/Geek202/pack-it)",
 );
 
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+#[derive(thiserror::Error, Debug)]
+#[error("{0} is not available offline")]
+pub struct OfflineError(pub String);
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Fails straight away in offline mode, rather than waiting for a request to `what` to time out.
+pub fn ensure_online(what: &str) -> Result<()> {
+    if is_offline() {
+        return Err(OfflineError(what.to_string()).into());
+    }
+    Ok(())
+}
+
 pub fn print_hello() {
     let hello = " Welcome to pack-it v0.1 ";
 
@@ -41,10 +64,12 @@ pub fn complete(message: &str) {
     println!("🎉 {}", message.green());
 }
 
-pub async fn hash_from_url(url: &str) -> Result<String> {
+pub async fn hash_from_url(url: &str) -> Result<FileHashes> {
+    ensure_online(url)?;
     let data = reqwest::get(url).await?
         .bytes().await?;
 
-    let digest = sha1::Sha1::digest(&*data);
-    Ok(format!("{:02x}", digest))
+    let mut hasher = FileHasher::new(&[HashAlgorithm::Sha1, HashAlgorithm::Sha512]);
+    hasher.update(&data);
+    Ok(hasher.finish())
 }